@@ -0,0 +1,300 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::Shutdown;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use getopts::Options;
+use getopts::ParsingStyle;
+use serde_json;
+use serde_json::Value;
+
+use server::DEFAULT_DATA_DIRECTORY;
+use server::commands::quote_arg;
+use server::read_config_file;
+use server::transport::DEFAULT_HOSTNAME;
+use server::transport::DEFAULT_PORT;
+use server::transport::DEFAULT_SOCKET_MODE;
+use server::transport::Transport;
+use server::transport::TransportOverrides;
+use server::transport::transport_from_config;
+
+const USAGE: &str = "Usage: enigma client [options] <command> [<argument>...]";
+const DEFAULT_CONFIG_FILE: &str = "enigma.yaml";
+
+/// How long the client waits to connect and for the reply unless `--timeout` says otherwise.
+const DEFAULT_TIMEOUT_SECONDS: u64 = 30;
+
+/// How the reply is printed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum OutputFormat {
+    /// One line per item, tags as `file:line: name`.
+    Text,
+    /// Indented JSON.
+    Json,
+    /// The elisp reply, as the server wrote it.
+    Elisp,
+}
+
+/// Sends the request in `args`, everything after `enigma client`, to a running server and prints
+/// the reply. Returns the exit code: 1 for error replies and failed connections, 2 for bad usage.
+pub fn run(args: &[String]) -> i32 {
+    let mut options = Options::new();
+    // options after the command name are the command's own
+    options.parsing_style(ParsingStyle::StopAtFirstFree);
+    options.optopt("c", "config", "connect to the server configured in FILE instead of enigma.yaml", "FILE");
+    options.optopt("", "host", "connect over TCP to HOST", "HOST");
+    options.optopt("p", "port", "connect over TCP to PORT", "PORT");
+    options.optopt("s", "socket", "connect to the Unix socket at PATH", "PATH");
+    options.optopt("f", "format", "print the reply as text, json or elisp", "FORMAT");
+    options.optopt("t", "timeout", "give up on the server after SECONDS, 30 by default, or never with 0", "SECONDS");
+    options.optflag("h", "help", "print this help and exit");
+
+    let matches = match options.parse(args) {
+        Ok(v) => v,
+        Err(e) => return usage_error(&options, &e.to_string()),
+    };
+    if matches.opt_present("help") {
+        print!("{}", options.usage(USAGE));
+        return 0;
+    }
+    if matches.free.is_empty() {
+        return usage_error(&options, "Missing command");
+    }
+
+    let format = match matches.opt_str("format").as_ref().map(|v| &v[..]) {
+        None | Some("text") => OutputFormat::Text,
+        Some("json") => OutputFormat::Json,
+        Some("elisp") => OutputFormat::Elisp,
+        Some(other) => return usage_error(&options, &format!("Unknown format '{}'", other)),
+    };
+    let port = match matches.opt_str("port").map(|v| v.parse::<usize>()) {
+        None => None,
+        Some(Ok(v)) => Some(v),
+        Some(Err(_)) => return usage_error(&options, "The port must be a number"),
+    };
+    let timeout = match matches.opt_str("timeout").map(|v| v.parse::<u64>()) {
+        None => Some(Duration::from_secs(DEFAULT_TIMEOUT_SECONDS)),
+        Some(Ok(0)) => None,
+        Some(Ok(v)) => Some(Duration::from_secs(v)),
+        Some(Err(_)) => return usage_error(&options, "The timeout must be a number of seconds"),
+    };
+
+    let transport = match matches.opt_str("socket") {
+        Some(path) => Ok(Transport::Unix { path: PathBuf::from(path), mode: DEFAULT_SOCKET_MODE }),
+        None => configured_transport(matches.opt_str("config")).map(|v| {
+            TransportOverrides { stdio: false, hostname: matches.opt_str("host"), port }.apply(v)
+        }),
+    };
+    let result = transport
+        .and_then(|transport| {
+            let request = request_line(&matches.free, format);
+            send(&transport, &request, timeout).map_err(|e| format!("Failed to send request to {}: {}", transport, e))
+        })
+        .and_then(|reply| render(&reply, format));
+
+    match result {
+        Ok(output) => {
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("enigma: {}", e);
+            1
+        }
+    }
+}
+
+fn usage_error(options: &Options, message: &str) -> i32 {
+    eprint!("{}\n\n{}", message, options.usage(USAGE));
+    2
+}
+
+/// The transport of the server in the config file. Without a config file the server is expected
+/// on the default TCP port, unless the file was named explicitly.
+fn configured_transport(config_file: Option<String>) -> Result<Transport, String> {
+    let explicit = config_file.is_some();
+    let config_file = config_file.unwrap_or_else(|| DEFAULT_CONFIG_FILE.to_string());
+    if !explicit && !PathBuf::from(&config_file).exists() {
+        return Ok(Transport::Tcp { hostname: DEFAULT_HOSTNAME.to_string(), port: DEFAULT_PORT });
+    }
+
+    let doc = read_config_file(&config_file)?;
+    let data_directory = doc["config"]["data_directory"].as_str().unwrap_or(DEFAULT_DATA_DIRECTORY);
+    Ok(transport_from_config(&doc["config"], data_directory))
+}
+
+/// The request for the command and its arguments, asking for the reply in the format it is
+/// printed from.
+fn request_line(args: &[String], format: OutputFormat) -> String {
+    let reply_format = if format == OutputFormat::Elisp { "elisp" } else { "json" };
+    let args: Vec<String> = args.iter().map(|v| quote_arg(v)).collect();
+    format!("--format {} {}", reply_format, args.join(" "))
+}
+
+/// Sends the request and waits up to `timeout` to connect and for the reply. A server answers
+/// one text request per connection and then closes it, so the reply is everything read until
+/// the end of the stream.
+fn send(transport: &Transport, request: &str, timeout: Option<Duration>) -> io::Result<String> {
+    match *transport {
+        Transport::Tcp { ref hostname, port } => {
+            let mut stream = connect_tcp(&format!("{}:{}", hostname, port), timeout)?;
+            stream.set_read_timeout(timeout)?;
+            stream.set_write_timeout(timeout)?;
+            stream.write_all(format!("{}\n", request).as_bytes())?;
+            stream.shutdown(Shutdown::Write)?;
+            read_reply(stream)
+        }
+        Transport::Unix { ref path, .. } => send_unix(path, request, timeout),
+        Transport::Stdio => Err(io::Error::new(io::ErrorKind::InvalidInput, "Servers on stdio take no connections")),
+    }
+}
+
+/// Connects to the first address of `address` which accepts within `timeout`.
+fn connect_tcp(address: &str, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let timeout = match timeout {
+        None => return TcpStream::connect(address),
+        Some(v) => v,
+    };
+    let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no address", address));
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(unix)]
+fn send_unix(path: &Path, request: &str, timeout: Option<Duration>) -> io::Result<String> {
+    use std::os::unix::net::UnixStream;
+
+    // connecting to a local socket fails right away rather than waiting
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    stream.write_all(format!("{}\n", request).as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+    read_reply(stream)
+}
+
+#[cfg(not(unix))]
+fn send_unix(_path: &Path, _request: &str, _timeout: Option<Duration>) -> io::Result<String> {
+    Err(io::Error::new(io::ErrorKind::Other, "Unix domain sockets are not supported on this platform"))
+}
+
+fn read_reply<R: Read>(mut stream: R) -> io::Result<String> {
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).map_err(|e| match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(io::ErrorKind::TimedOut, "Timed out waiting for the reply"),
+        _ => e,
+    })?;
+    if reply.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "The server closed the connection without a reply"));
+    }
+    Ok(reply)
+}
+
+/// The reply as printed in `format`, or the message of an error reply.
+fn render(reply: &str, format: OutputFormat) -> Result<String, String> {
+    if format == OutputFormat::Elisp {
+        if reply.starts_with("(error ") {
+            return Err(reply.to_string());
+        }
+        return Ok(reply.to_string());
+    }
+
+    let value: Value = serde_json::from_str(reply).map_err(|e| format!("Malformed reply {}: {}", reply, e))?;
+    if value.get("type").and_then(|v| v.as_str()) == Some("error") {
+        let kind = value.get("kind").and_then(|v| v.as_str()).unwrap_or("error");
+        let message = value.get("message").and_then(|v| v.as_str()).unwrap_or("");
+        return Err(format!("{}: {}", kind, message));
+    }
+
+    match format {
+        OutputFormat::Json => serde_json::to_string_pretty(&value).map_err(|e| e.to_string()),
+        _ => Ok(text(&value)),
+    }
+}
+
+/// Lists one item per line and tags, or anything else with a location, as `file:line: name`.
+fn text(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref v) => v.clone(),
+        Value::Array(ref items) => items.iter().map(text).collect::<Vec<String>>().join("\n"),
+        Value::Object(ref properties) => {
+            let string = |key: &str| properties.get(key).map(|v| match *v {
+                Value::String(ref v) => v.clone(),
+                ref other => other.to_string(),
+            });
+            if let (Some(file), Some(line)) = (string("file"), string("line")) {
+                return match string("name") {
+                    None => format!("{}:{}", file, line),
+                    Some(name) => format!("{}:{}: {}", file, line, name),
+                };
+            }
+
+            // the tags found so far, then why there may be more
+            if let (Some("truncated"), Some(tags)) = (properties.get("type").and_then(|v| v.as_str()), properties.get("tags")) {
+                return format!("{}\n{}", text(tags), string("message").unwrap_or_default());
+            }
+
+            let mut fields: Vec<String> = string("type").into_iter().collect();
+            fields.extend(properties.keys().filter(|&v| v != "type").map(|v| format!("{}={}", v, string(v).unwrap_or_default())));
+            fields.join(" ")
+        }
+        ref other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_quote_request_arguments() {
+        let args: Vec<String> = vec!["find".to_string(), "-n".to_string(), "5".to_string(), "kind:function prototype".to_string()];
+        assert_eq!("--format json find -n 5 \"kind:function prototype\"", request_line(&args, OutputFormat::Text));
+        assert_eq!("--format elisp find -n 5 \"kind:function prototype\"", request_line(&args, OutputFormat::Elisp));
+        assert_eq!("--format json echo \"\\\"\"", request_line(&["echo".to_string(), "\"".to_string()], OutputFormat::Json));
+    }
+
+    #[test]
+    fn should_render_replies_as_text() {
+        let reply = "[{\"type\":\"tag\",\"name\":\"Tick\",\"file\":\"Actor.h\",\"line\":12},\
+                     {\"type\":\"loaded\",\"file\":\"tags\",\"tags\":3}]";
+        assert_eq!(Ok("Actor.h:12: Tick\nloaded file=tags tags=3".to_string()), render(reply, OutputFormat::Text));
+        assert_eq!(Ok(String::new()), render("null", OutputFormat::Text));
+        let reply = "{\"type\":\"truncated\",\"tags\":[{\"type\":\"tag\",\"name\":\"Tick\",\"file\":\"Actor.h\",\"line\":12}],\
+                     \"message\":\"more tags may match\"}";
+        assert_eq!(Ok("Actor.h:12: Tick\nmore tags may match".to_string()), render(reply, OutputFormat::Text));
+        assert_eq!(Ok("{\n  \"file\": \"tags\"\n}".to_string()), render("{\"file\":\"tags\"}", OutputFormat::Json));
+        assert_eq!(Ok("(tag :name \"Tick\")".to_string()), render("(tag :name \"Tick\")", OutputFormat::Elisp));
+    }
+
+    #[test]
+    fn should_fail_on_error_replies() {
+        let reply = "{\"type\":\"error\",\"kind\":\"not-found\",\"message\":\"Unrecognized command 'bogus'\"}";
+        assert_eq!(Err("not-found: Unrecognized command 'bogus'".to_string()), render(reply, OutputFormat::Text));
+        assert_eq!(Err("not-found: Unrecognized command 'bogus'".to_string()), render(reply, OutputFormat::Json));
+        assert!(render("(error :kind usage :message \"usage: outline <file>\")", OutputFormat::Elisp).is_err());
+    }
+
+    #[test]
+    fn should_time_out_waiting_for_replies() {
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = Transport::Tcp { hostname: "127.0.0.1".to_string(), port: listener.local_addr().unwrap().port() as usize };
+        let error = send(&transport, "ping", Some(Duration::from_millis(100))).unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, error.kind());
+    }
+}
@@ -2,34 +2,130 @@
 extern crate log;
 extern crate simplelog;
 extern crate getopts;
+extern crate regex;
+#[macro_use]
+extern crate serde_json;
+extern crate signal_hook;
 
 mod tags;
 mod search;
+mod query;
 mod server;
+mod client;
 
 
+use getopts::Options;
 use simplelog::*;
+use std::env;
 use std::fs::File;
+use std::io;
+use std::process;
 
 use server::Server;
+use server::transport::TransportOverrides;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+const USAGE: &str = "Usage: enigma [options]\n       enigma client [options] <command> [<argument>...]";
 
 fn main() {
-    init_logging();
-    run_server();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(|v| &v[..]) == Some("client") {
+        process::exit(client::run(&args[1..]));
+    }
+
+    let mut options = Options::new();
+    options.optopt("c", "config", "read the config from FILE instead of enigma.yaml", "FILE");
+    options.optopt("", "host", "listen for TCP connections on HOST", "HOST");
+    options.optopt("p", "port", "listen for TCP connections on PORT", "PORT");
+    options.optflag("", "stdio", "answer requests from stdin, one per line, on stdout");
+    options.optflag("", "lsp", "serve the Language Server Protocol over stdin and stdout");
+    options.optopt("", "log-file", "write the log to FILE instead of enigma.log", "FILE");
+    options.optopt("", "log-level", "log at LEVEL: off, error, warn, info (the default), debug or trace", "LEVEL");
+    options.optflag("q", "quiet", "only write the log to the log file");
+    options.optflag("V", "version", "print the version and exit");
+    options.optflag("h", "help", "print this help and exit");
+
+    let matches = match options.parse(&args) {
+        Ok(v) => v,
+        Err(e) => exit_with_usage(&options, &e.to_string()),
+    };
+    if matches.opt_present("help") {
+        print!("{}", options.usage(USAGE));
+        return;
+    }
+    if matches.opt_present("version") {
+        println!("enigma {}", VERSION);
+        return;
+    }
+    if !matches.free.is_empty() {
+        exit_with_usage(&options, &format!("Unexpected argument '{}'", matches.free[0]));
+    }
+
+    let log_level = match matches.opt_str("log-level").map(|v| v.parse::<LogLevelFilter>()) {
+        None => LogLevelFilter::Info,
+        Some(Ok(v)) => v,
+        Some(Err(_)) => exit_with_usage(&options, "Unknown log level"),
+    };
+    let port = match matches.opt_str("port").map(|v| v.parse::<usize>()) {
+        None => None,
+        Some(Ok(v)) => Some(v),
+        Some(Err(_)) => exit_with_usage(&options, "The port must be a number"),
+    };
+    let lsp = matches.opt_present("lsp");
+    let overrides = TransportOverrides {
+        stdio: matches.opt_present("stdio"),
+        hostname: matches.opt_str("host"),
+        port,
+    };
+
+    // clients over stdio read stdout, so only the log file is written to
+    let log_file = matches.opt_str("log-file").unwrap_or_else(|| "enigma.log".to_string());
+    let log_to_terminal = !(lsp || overrides.stdio || matches.opt_present("quiet"));
+    if let Err(e) = init_logging(&log_file, log_level, log_to_terminal) {
+        exit_with_error(&format!("Failed to open log file {}: {}", log_file, e));
+    }
+
+    let config_file = matches.opt_str("config").unwrap_or_else(|| "enigma.yaml".to_string());
+    let result = if lsp {
+        run_lsp_server(&config_file)
+    } else {
+        run_server(&config_file, &overrides)
+    };
+    if let Err(e) = result {
+        error!("{}", e);
+        exit_with_error(&e);
+    }
+}
+
+fn exit_with_usage(options: &Options, message: &str) -> ! {
+    eprint!("{}\n\n{}", message, options.usage(USAGE));
+    process::exit(2);
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("enigma: {}", message);
+    process::exit(1);
+}
+
+fn init_logging(log_file: &str, log_level: LogLevelFilter, log_to_terminal: bool) -> io::Result<()> {
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![
+        WriteLogger::new(log_level, Config::default(), File::create(log_file)?),
+    ];
+    if log_to_terminal {
+        if let Some(logger) = TermLogger::new(log_level, Config::default()) {
+            loggers.push(logger);
+        }
+    }
+    CombinedLogger::init(loggers).map_err(|e| io::Error::other(e.to_string()))
 }
 
-fn init_logging() {
-    CombinedLogger::init(
-        vec![
-            TermLogger::new(LogLevelFilter::Trace, Config::default()).unwrap(),
-            WriteLogger::new(LogLevelFilter::Trace, Config::default(), File::create("enigma.log").unwrap()),
-        ]
-    ).unwrap();    
+fn run_server(config_file: &str, overrides: &TransportOverrides) -> Result<(), String> {
+    info!("Running enigma {}", VERSION);
+    Server::start_with_config_file(config_file, overrides)
 }
 
-fn run_server() {
-    info!("Running enigma {}", VERSION);    
-    Server::start_with_config_file("enigma.yaml");
+fn run_lsp_server(config_file: &str) -> Result<(), String> {
+    info!("Running enigma {} as a language server", VERSION);
+    Server::start_lsp_with_config_file(config_file)
 }
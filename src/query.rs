@@ -0,0 +1,256 @@
+use search::EXACT_MATCH_SCORE;
+use search::match_abbrev_positions;
+use search::Glob;
+use tags::TagDefinition;
+use tags::TagKind;
+use tags::tag_kind_from_str;
+
+/// A tag search made of filters which must all match. Filters are written `key:value`, with
+/// `|` separating alternatives, e.g. `name:Act* kind:class|struct file:**/GameFramework/*.h`.
+/// Any other term is matched as a name abbreviation (see `match_abbrev_positions`).
+pub struct Query {
+    filters: Vec<Filter>,
+}
+
+/// How well a tag matched a query.
+#[derive(Debug, PartialEq)]
+pub struct QueryMatch {
+    /// How well the tag's name matches the name terms, higher is better.
+    pub score: usize,
+    /// The character ranges of the name matched by the first abbreviation term, for
+    /// highlighting. Qualified terms report ranges of the qualified name.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+#[derive(Clone)]
+enum Filter {
+    NameAbbrev(String),
+    Name(Vec<(String, Glob)>),
+    Kind(Vec<TagKind>),
+    File(Vec<Glob>),
+    Scope(Vec<Glob>),
+    Access(Vec<String>),
+    Language(Vec<String>),
+    Signature(Vec<Glob>),
+}
+
+/// The keys of the filters a query term may start with, followed by a single `:`.
+const FILTER_KEYS: [&str; 7] = ["name", "kind", "file", "scope", "access", "lang", "sig"];
+
+impl Query {
+    pub fn parse(terms: &[&str]) -> Result<Query, String> {
+        let mut filters = Vec::new();
+
+        for term in terms {
+            // a `::` makes a qualified name rather than a filter
+            let (key, value) = match term.find(':') {
+                Some(v) if FILTER_KEYS.contains(&&term[..v]) && !term[v + 1..].starts_with(':') => (&term[..v], &term[v + 1..]),
+                _ => ("", ""),
+            };
+            let alternatives = || -> Result<Vec<String>, String> {
+                let alternatives: Vec<String> = value.split('|').filter(|v| !v.is_empty()).map(|v| v.to_string()).collect();
+                if alternatives.is_empty() {
+                    return Err(format!("Missing value for '{}' filter", key));
+                }
+                Ok(alternatives)
+            };
+            let globs = || -> Result<Vec<Glob>, String> {
+                alternatives()?.iter().map(|v| Glob::new(v)).collect()
+            };
+
+            let filter = match key {
+                "name" => {
+                    let patterns = alternatives()?;
+                    Filter::Name(patterns.iter().cloned().zip(globs()?).collect())
+                }
+                "kind" => {
+                    let mut kinds = Vec::new();
+                    for kind_name in alternatives()? {
+                        match tag_kind_from_str(&kind_name) {
+                            TagKind::Unknown => return Err(format!("Unknown tag kind '{}'", kind_name)),
+                            kind => kinds.push(kind),
+                        }
+                    }
+                    Filter::Kind(kinds)
+                }
+                "file" => Filter::File(globs()?),
+                "scope" => Filter::Scope(globs()?),
+                "access" => Filter::Access(alternatives()?),
+                "lang" => Filter::Language(alternatives()?),
+                "sig" => Filter::Signature(globs()?),
+                _ => Filter::NameAbbrev(term.to_string()),
+            };
+            filters.push(filter);
+        }
+
+        if filters.is_empty() {
+            return Err("Empty query".to_string());
+        }
+        Ok(Query { filters })
+    }
+
+    pub fn matches(&self, tag: &TagDefinition) -> bool {
+        self.match_tag(tag).is_some()
+    }
+
+    /// How well the tag matches, or None if any filter rejects it. Each name term is matched
+    /// once, for both the score and the ranges.
+    pub fn match_tag(&self, tag: &TagDefinition) -> Option<QueryMatch> {
+        let mut result = QueryMatch { score: 0, ranges: Vec::new() };
+        for filter in &self.filters {
+            match *filter {
+                Filter::NameAbbrev(ref abbrev) => {
+                    let abbrev_match = match_abbrev_positions(&name_of(tag, abbrev), abbrev)?;
+                    result.score = result.score.max(abbrev_match.score);
+                    // matched ranges are never empty, so these are the first term's
+                    if result.ranges.is_empty() {
+                        result.ranges = abbrev_match.ranges;
+                    }
+                }
+                Filter::Name(ref patterns) => {
+                    let mut is_match = false;
+                    for (pattern, glob) in patterns {
+                        let name = name_of(tag, pattern);
+                        if name == *pattern {
+                            result.score = result.score.max(EXACT_MATCH_SCORE);
+                        }
+                        is_match = is_match || glob.matches(&name);
+                    }
+                    if !is_match {
+                        return None;
+                    }
+                }
+                ref other => {
+                    if !other.matches(tag) {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(result)
+    }
+
+    /// The query made of only the name terms, if there are any.
+    pub fn name_query(&self) -> Option<Query> {
+        let filters: Vec<Filter> = self.filters.iter()
+            .filter(|v| matches!(**v, Filter::NameAbbrev(_) | Filter::Name(_)))
+            .cloned()
+            .collect();
+        if filters.is_empty() { None } else { Some(Query { filters }) }
+    }
+
+    /// The name the query looks for, if it names one rather than a pattern. Used to suggest
+    /// similar names when nothing matches.
+    pub fn name_term(&self) -> Option<&str> {
+        for filter in &self.filters {
+            let name = match *filter {
+                Filter::NameAbbrev(ref abbrev) => &abbrev[..],
+                Filter::Name(ref patterns) if !patterns[0].0.contains(['*', '?']) => &patterns[0].0[..],
+                _ => continue,
+            };
+            return Some(match name.rfind("::") {
+                None => name,
+                Some(v) => &name[v + 2..],
+            });
+        }
+        None
+    }
+}
+
+impl Filter {
+    /// Whether the tag passes a filter other than a name filter.
+    fn matches(&self, tag: &TagDefinition) -> bool {
+        match *self {
+            Filter::NameAbbrev(_) | Filter::Name(_) => true,
+            Filter::Kind(ref kinds) => kinds.contains(&tag.kind()),
+            Filter::File(ref patterns) => patterns.iter().any(|v| v.matches(tag.source_file())),
+            Filter::Scope(ref patterns) => match tag.scope_name() {
+                None => false,
+                Some(scope) => patterns.iter().any(|v| v.matches(scope)),
+            },
+            Filter::Access(ref values) => match tag.field("access") {
+                None => false,
+                Some(access) => values.iter().any(|v| v.eq_ignore_ascii_case(access)),
+            },
+            Filter::Language(ref values) => match tag.field("language") {
+                None => false,
+                Some(language) => values.iter().any(|v| v.eq_ignore_ascii_case(language)),
+            },
+            Filter::Signature(ref patterns) => match tag.signature() {
+                None => false,
+                Some(signature) => patterns.iter().any(|v| v.matches(signature)),
+            },
+        }
+    }
+}
+
+/// Qualified names are only matched against queries that are themselves qualified.
+fn name_of(tag: &TagDefinition, query: &str) -> String {
+    if query.contains("::") { tag.qualified_name() } else { tag.name().to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(line: &str) -> TagDefinition {
+        TagDefinition::from_string(line.to_string())
+    }
+
+    #[test]
+    fn should_combine_filters() {
+        let actor = tag("AActor\tEngine/Classes/GameFramework/Actor.h\t/^class AActor$/;\"\tc\tline:40\tlanguage:C++");
+        let tick = tag("Tick\tEngine/Classes/GameFramework/Actor.h\t/^\tvirtual void Tick(float DeltaSeconds);$/;\"\tp\tline:50\
+                        \tclass:AActor\taccess:public\tsignature:(float DeltaSeconds)");
+
+        let query = Query::parse(&["name:AAct*", "kind:class|struct", "file:**/GameFramework/*.h", "lang:c++"]).unwrap();
+        assert!(query.matches(&actor));
+        assert!(!query.matches(&tick));
+
+        let query = Query::parse(&["scope:AActor", "access:public", "sig:*float*"]).unwrap();
+        assert!(!query.matches(&actor));
+        assert!(query.matches(&tick));
+    }
+
+    #[test]
+    fn should_match_bare_terms_as_name_abbreviation() {
+        let tick = tag("TickActor\tActor.h\t/^\tvoid TickActor();$/;\"\tp\tline:50\tclass:AActor");
+        assert!(Query::parse(&["Ti"]).unwrap().matches(&tick));
+        assert!(Query::parse(&["TiAc"]).unwrap().matches(&tick));
+        assert!(Query::parse(&["AActor::TA"]).unwrap().matches(&tick));
+        assert!(!Query::parse(&["Ca"]).unwrap().matches(&tick));
+        assert_eq!(vec![(0, 2), (4, 6)], Query::parse(&["TiAc"]).unwrap().match_tag(&tick).unwrap().ranges);
+    }
+
+    #[test]
+    fn should_score_exact_names_higher() {
+        let tick = tag("Tick\tActor.h\t/^\tvoid Tick();$/;\"\tp\tline:50");
+        let tick_actor = tag("TickActor\tActor.h\t/^\tvoid TickActor();$/;\"\tp\tline:50");
+        let score = |query, tag| Query::parse(&[query]).unwrap().match_tag(tag).unwrap().score;
+        assert!(score("Tick", &tick) > score("Tick", &tick_actor));
+        assert!(score("Tick", &tick_actor) > score("TAc", &tick_actor));
+        assert_eq!(score("name:Tick", &tick), score("Tick", &tick));
+    }
+
+    #[test]
+    fn should_find_name_term() {
+        assert_eq!(Some("Tick"), Query::parse(&["kind:function", "AActor::Tick"]).unwrap().name_term());
+        assert_eq!(Some("Tick"), Query::parse(&["name:Tick"]).unwrap().name_term());
+        assert_eq!(None, Query::parse(&["name:Ti*"]).unwrap().name_term());
+    }
+
+    #[test]
+    fn should_match_qualified_names_rather_than_filters() {
+        let spawn_actor = tag("SpawnActor\tWorld.h\t/^\tAActor* SpawnActor();$/;\"\tp\tline:50\tclass:UWorld");
+        assert!(Query::parse(&["UWorld::SpawnActor"]).unwrap().matches(&spawn_actor));
+        assert!(Query::parse(&["name:UWorld::SpawnActor"]).unwrap().matches(&spawn_actor));
+        assert!(Query::parse(&["scope:UWorld", "kind::SpawnActor"]).is_ok());
+    }
+
+    #[test]
+    fn should_reject_malformed_filters() {
+        assert!(Query::parse(&["kind:bogus"]).is_err());
+        assert!(Query::parse(&["name:"]).is_err());
+        assert!(Query::parse(&[]).is_err());
+    }
+}
@@ -1,35 +1,233 @@
+/// Where and how well an abbreviation matched a value.
+#[derive(Debug, PartialEq)]
+pub struct AbbrevMatch {
+    /// Higher is better: prefix matches beat word-by-word matches, which are ranked by how few
+    /// words they need and how much of the value they leave out.
+    pub score: usize,
+    /// Matched character ranges of the value, as (start, end) with the end excluded.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+#[cfg(test)]
 pub fn match_abbrev(value: &str, query: &str) -> bool {
+    match_abbrev_positions(value, query).is_some()
+}
 
-    if value.to_lowercase().starts_with(query.to_lowercase().as_str()) {
-        return true;
+/// Matches `query` as an abbreviation of `value`, e.g. `ProjSpLoc`, `PSL` or `proj_spawn` for
+/// `ProjectileSpawnLocation`. The query is consumed in chunks, each a case insensitive prefix
+/// of a word of the value. Words start at CamelCase humps, after underscores and other
+/// separators, and where digits begin or end. Positions are in characters, not bytes.
+pub fn match_abbrev_positions(value: &str, query: &str) -> Option<AbbrevMatch> {
+    let value: Vec<char> = value.chars().collect();
+    let raw_query: Vec<char> = query.chars().collect();
+    let is_prefix = !raw_query.is_empty() && raw_query.len() <= value.len()
+        && value.iter().zip(raw_query.iter()).all(|(&v, &q)| fold_case(v) == fold_case(q));
+    if is_prefix {
+        let score = if value.len() == raw_query.len() { EXACT_MATCH_SCORE } else { PREFIX_SCORE };
+        return Some(AbbrevMatch {
+            score: score.saturating_sub(value.len() - raw_query.len()),
+            ranges: vec![(0, raw_query.len())],
+        });
     }
 
-    let mut parts = Vec::new();
-    let mut part = String::from("");
-    for current_char in query.chars() {
-        if current_char.is_uppercase() {
-            if !part.is_empty() {
-                parts.push(part);
+    // underscores only separate the chunks, which match words wherever they start
+    let query: Vec<char> = raw_query.into_iter().filter(|&c| c != '_').collect();
+    if query.is_empty() || query.len() > value.len() {
+        return None;
+    }
+
+    let word_starts: Vec<usize> = (0..value.len()).filter(|&i| is_word_start(&value, i)).collect();
+    let mut failed = vec![false; (query.len() + 1) * (value.len() + 1)];
+    let mut ranges = Vec::new();
+    if !match_words(&value, &query, &word_starts, 0, 0, &mut failed, &mut ranges) {
+        return None;
+    }
+
+    ranges.reverse();
+    let unmatched = value.len() - query.len();
+    Some(AbbrevMatch {
+        score: WORDS_SCORE.saturating_sub(10 * ranges.len() + unmatched),
+        ranges,
+    })
+}
+
+pub const EXACT_MATCH_SCORE: usize = 2000;
+const PREFIX_SCORE: usize = 1000;
+const WORDS_SCORE: usize = 500;
+
+/// Matches `query[query_index..]` starting with the word at `word_starts[word_index]` or a
+/// later one, trying the longest chunk first. Ranges are pushed in reverse order.
+fn match_words(value: &[char],
+               query: &[char],
+               word_starts: &[usize],
+               query_index: usize,
+               word_index: usize,
+               failed: &mut Vec<bool>,
+               ranges: &mut Vec<(usize, usize)>) -> bool {
+    for (i, &start) in word_starts.iter().enumerate().skip(word_index) {
+        let key = query_index * (value.len() + 1) + start;
+        if failed[key] {
+            continue;
+        }
+
+        let mut chunk_length = 0;
+        while query_index + chunk_length < query.len() && start + chunk_length < value.len()
+            && fold_case(value[start + chunk_length]) == fold_case(query[query_index + chunk_length]) {
+            chunk_length += 1;
+        }
+
+        for length in (1..chunk_length + 1).rev() {
+            if query_index + length == query.len()
+                || match_words(value, query, word_starts, query_index + length, i + 1, failed, ranges) {
+                ranges.push((start, start + length));
+                return true;
             }
-            part = String::from("");
         }
-        part.push(current_char);
+        failed[key] = true;
     }
-    if !part.is_empty() {
-        parts.push(part);
+
+    false
+}
+
+/// Splits `value` into its words, starting new words where `match_abbrev` does.
+pub fn split_words(value: &str) -> Vec<String> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !word.is_empty() && (is_word_start(&chars, i) || !c.is_alphanumeric()) {
+            words.push(word);
+            word = String::new();
+        }
+        if c.is_alphanumeric() {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn is_word_start(value: &[char], index: usize) -> bool {
+    let current = value[index];
+    if !current.is_alphanumeric() {
+        return false;
+    }
+    if index == 0 {
+        return true;
+    }
+
+    let previous = value[index - 1];
+    let next = value.get(index + 1);
+    !previous.is_alphanumeric()
+        || (current.is_uppercase() && !previous.is_uppercase())
+        || (current.is_uppercase() && previous.is_uppercase() && next.map(|v| v.is_lowercase()).unwrap_or(false))
+        || (current.is_numeric() != previous.is_numeric())
+}
+
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+pub fn match_glob(value: &str, pattern: &str) -> bool {
+    Glob::new(pattern).unwrap().matches(value)
+}
+
+/// Glob patterns have at most this many wildcards and characters, so that the states of a
+/// match fit into a bit set.
+const MAX_GLOB_TOKENS: usize = 127;
+
+/// A case insensitive glob pattern, compiled once to match many values. `*` and `?` don't
+/// match path separators, `**` matches across directories and `**/` also matches no directory
+/// at all.
+#[derive(Clone)]
+pub struct Glob {
+    tokens: Vec<GlobToken>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GlobToken {
+    Char(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyName,
+    /// `**`
+    AnyPath,
+    /// The start of `**/`, followed by `**` and `/`, which may all be skipped.
+    AnyDirectories,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, String> {
+        let chars: Vec<char> = pattern.chars().map(fold_case).collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let token = match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') && chars.get(i + 2) == Some(&'/') => {
+                    tokens.push(GlobToken::AnyDirectories);
+                    tokens.push(GlobToken::AnyPath);
+                    i += 2;
+                    GlobToken::Char('/')
+                }
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    i += 1;
+                    GlobToken::AnyPath
+                }
+                '*' => GlobToken::AnyName,
+                '?' => GlobToken::AnyChar,
+                c => GlobToken::Char(c),
+            };
+            tokens.push(token);
+            i += 1;
+        }
+        if tokens.len() > MAX_GLOB_TOKENS {
+            return Err(format!("Pattern '{}' is too long", pattern));
+        }
+        Ok(Glob { tokens })
     }
 
-    let mut index = 0;
-    for part in parts {
-        match value[index..].find(&part) {
-            None => return false,
-            Some(v) => {
-                index = index + v;
+    /// Follows all ways the pattern can match at once, with bit `i` of the states set when
+    /// the value so far can be matched up to token `i`. This takes time proportional to the
+    /// product of the lengths at worst, without allocating.
+    pub fn matches(&self, value: &str) -> bool {
+        let mut states = self.skip_stars(1);
+        for c in value.chars().map(fold_case) {
+            let mut next_states = 0;
+            for (i, &token) in self.tokens.iter().enumerate().filter(|&(i, _)| states & (1 << i) != 0) {
+                next_states |= match token {
+                    GlobToken::Char(expected) if c == expected => 1 << (i + 1),
+                    GlobToken::AnyChar if c != '/' => 1 << (i + 1),
+                    GlobToken::AnyName if c != '/' => 1 << i,
+                    GlobToken::AnyPath => 1 << i,
+                    _ => 0,
+                };
+            }
+            if next_states == 0 {
+                return false;
             }
+            states = self.skip_stars(next_states);
         }
+        states & (1 << self.tokens.len()) != 0
     }
 
-    return true;
+    /// The states with those after stars added, as stars may match nothing.
+    fn skip_stars(&self, mut states: u128) -> u128 {
+        for (i, &token) in self.tokens.iter().enumerate() {
+            if states & (1 << i) == 0 {
+                continue;
+            }
+            match token {
+                GlobToken::AnyName | GlobToken::AnyPath => states |= 1 << (i + 1),
+                GlobToken::AnyDirectories => states |= (1 << (i + 1)) | (1 << (i + 3)),
+                _ => {}
+            }
+        }
+        states
+    }
 }
 
 #[cfg(test)]
@@ -52,4 +250,91 @@ mod tests {
         assert_eq!(false, match_abbrev("ProjectileSpawnLocation", "ProjSpLc"));
         assert_eq!(false, match_abbrev("ProjectileSpawnLocation", "PSLC"));
     }
+
+    #[test]
+    fn match_abbrev_should_pass_word_boundaries() {
+        assert!(match_abbrev("projectile_spawn_location", "proj_spawn"));
+        assert!(match_abbrev("PROJECTILE_SPAWN_LOCATION", "PSL"));
+        assert!(match_abbrev("ProjectileSpawnLocation", "psl"));
+        assert!(match_abbrev("FVector2DHalf", "V2DH"));
+        assert!(match_abbrev("HTTPServerConfig", "SeCo"));
+        assert!(match_abbrev("ÜberSpawnLocation", "üsl"));
+    }
+
+    #[test]
+    fn match_abbrev_should_report_char_ranges() {
+        assert_eq!(vec![(0, 4), (10, 12), (15, 18)],
+                   match_abbrev_positions("ProjectileSpawnLocation", "ProjSpLoc").unwrap().ranges);
+        assert_eq!(vec![(0, 1), (4, 5)], match_abbrev_positions("ÜberSpawn", "ÜS").unwrap().ranges);
+        assert_eq!(vec![(0, 4), (11, 16)], match_abbrev_positions("projectile_spawn", "proj_spawn").unwrap().ranges);
+        assert_eq!(vec![(0, 4)], match_abbrev_positions("ProjectileSpawnLocation", "proj").unwrap().ranges);
+    }
+
+    #[test]
+    fn match_abbrev_should_score_tighter_matches_higher() {
+        let score = |value, query| match_abbrev_positions(value, query).unwrap().score;
+        assert!(score("Spawn", "Spawn") > score("SpawnLocation", "Spawn"));
+        assert!(score("SpawnLocation", "Spawn") > score("ProjectileSpawnLocation", "SpLoc"));
+        assert!(score("SpawnLocation", "SpLoc") > score("ProjectileSpawnLocation", "SpLoc"));
+    }
+
+    #[test]
+    fn match_abbrev_should_score_names_with_underscores_as_exact() {
+        let score = |value, query| match_abbrev_positions(value, query).unwrap().score;
+        assert_eq!(EXACT_MATCH_SCORE, score("my_func", "my_func"));
+        assert!(score("my_func", "my_func") > score("my_function", "my_func"));
+        assert!(score("my_function", "my_func") > score("myfunction_helper", "my_func"));
+        assert!(match_abbrev_positions("", "_").is_none());
+        assert!(match_abbrev_positions("my_func", "").is_none());
+    }
+
+    #[test]
+    fn split_words_should_split_at_boundaries() {
+        assert_eq!(vec!["Projectile", "Spawn", "Location"], split_words("ProjectileSpawnLocation"));
+        assert_eq!(vec!["HTTP", "Server", "2", "D"], split_words("HTTPServer2D"));
+        assert_eq!(vec!["SPAWN", "LOCATION"], split_words("SPAWN_LOCATION"));
+    }
+
+    #[test]
+    fn match_glob_should_pass() {
+        assert!(match_glob("AActor", "AAct*"));
+        assert!(match_glob("AActor", "aact?r"));
+        assert!(match_glob("Engine/Classes/GameFramework/Actor.h", "**/GameFramework/*.h"));
+        assert!(match_glob("GameFramework/Actor.h", "**/GameFramework/*.h"));
+        assert!(match_glob("(float DeltaTime)", "*float*"));
+    }
+
+    #[test]
+    fn match_glob_should_fail() {
+        assert!(!match_glob("AActor", "Act*"));
+        assert!(!match_glob("Engine/Classes/GameFramework/Actor.h", "*/GameFramework/*.h"));
+        assert!(!match_glob("GameFramework/Sub/Actor.h", "**/GameFramework/*.h"));
+    }
+
+    #[test]
+    fn match_glob_should_not_backtrack_exponentially() {
+        let value = "a".repeat(200);
+        assert!(!match_glob(&value, "*a*a*a*a*a*a*a*a*a*a*a*a*b"));
+        assert!(!match_glob(&value, "**a**a**a**a**a**a**a**a**a**b"));
+        assert!(match_glob(&value, "*a*a*a*a*a*a*a*a*a*a*a*a*"));
+    }
+
+    #[test]
+    fn match_glob_should_combine_kinds_of_stars() {
+        assert!(match_glob("Engine/Source/Actor/Actor.h", "**/Actor/*.h"));
+        assert!(match_glob("a/ab", "**a*b"));
+        assert!(match_glob("Engine/Public/Actor.h", "engine/**/*.h"));
+        assert!(match_glob("Engine/Actor.h", "Engine/**/*.h"));
+        assert!(!match_glob("Engine/Actor.cpp", "Engine/**/*.h"));
+        assert!(!match_glob("Engine/Public/Actor.h", "Engine/*.h"));
+        assert!(match_glob("Ünreal/Äctor.h", "ü*/ä?tor.h"));
+        assert!(!match_glob("Engine/xActor.h", "Engine/**/Actor.h"));
+        assert!(Glob::new(&"a".repeat(200)).is_err());
+    }
+
+    #[test]
+    fn match_abbrev_should_fail_on_inner_or_longer_queries() {
+        assert!(!match_abbrev("ProjectileSpawnLocation", "rojSp"));
+        assert!(!match_abbrev("Über", "Überall"));
+    }
 }
@@ -1,34 +1,52 @@
-pub fn split_args(v: &str) -> Vec<&str> {
+use std::mem;
+
+/// The whitespace separated arguments of a request, where an argument in quotes may contain
+/// whitespace, and `\"` and `\\` stand for a quote and a backslash. Fails on a quote which is
+/// never closed.
+pub fn split_args(v: &str) -> Result<Vec<String>, String> {
     let trimmed = v.trim();
 
-    let mut splits = Vec::new();    
-    let mut prev_split = 0 as usize;
+    let mut splits = Vec::new();
+    let mut split = String::new();
+    let mut in_split = false;
     let mut quoted = false;
-    let mut was_quote = false;
-    
-    for (i, c) in trimmed.chars().enumerate() {
+    let mut chars = trimmed.chars().peekable();
+
+    while let Some(c) = chars.next() {
         if c.is_whitespace() && !quoted {
-            if prev_split < i - 1 {
-                splits.push(get_split(trimmed, prev_split, i, was_quote));
-                was_quote = false;
+            if in_split {
+                splits.push(mem::take(&mut split));
+                in_split = false;
             }
-            prev_split = i+1
-        } else if c == '"' {
+            continue;
+        }
+
+        in_split = true;
+        if c == '"' {
             quoted = !quoted;
-            was_quote = true;
+        } else if c == '\\' && quoted && matches!(chars.peek(), Some('"') | Some('\\')) {
+            split.extend(chars.next());
+        } else {
+            split.push(c);
         }
     }
+    if quoted {
+        return Err(format!("Unbalanced quote in request {}", trimmed));
+    }
 
-    splits.push(get_split(trimmed, prev_split, trimmed.chars().count(), was_quote));
+    if in_split || splits.is_empty() {
+        splits.push(split);
+    }
 
-    splits
+    Ok(splits)
 }
 
-fn get_split(val: &str, from: usize, to: usize, was_quote: bool) -> &str {
-    if was_quote {
-        &val[from+1..to-1]
+/// The argument quoted so that `split_args` gives it back.
+pub fn quote_arg(v: &str) -> String {
+    if v.is_empty() || v.contains(|c: char| c.is_whitespace() || c == '"') {
+        format!("\"{}\"", v.replace('\\', "\\\\").replace('"', "\\\""))
     } else {
-        &val[from..to]
+        v.to_string()
     }
 }
 
@@ -39,30 +57,70 @@ mod tests {
 
     #[test]
     fn split_simple_words() {
-        assert_eq!(vec!("foo", "bar"), split_args("foo bar"));
+        assert_eq!(vec!("foo", "bar"), split_args("foo bar").unwrap());
+    }
+
+    #[test]
+    fn split_single_characters() {
+        assert_eq!(vec!("find", "-n", "5", "x"), split_args("find -n 5 x").unwrap());
     }
 
     #[test]
     fn split_skip_spaces() {
-        assert_eq!(vec!("foo", "bar", "zmosh"), split_args("foo    bar    zmosh"));
+        assert_eq!(vec!("foo", "bar", "zmosh"), split_args("foo    bar    zmosh").unwrap());
     }
     
     #[test]
     fn split_skip_leading_and_trailing_spaces() {
-        assert_eq!(vec!("foo", "bar"), split_args("    foo    bar"));
-        assert_eq!(vec!("foo", "bar"), split_args("foo    bar     "));
-        assert_eq!(vec!("foo", "bar"), split_args("    foo    bar     "));
+        assert_eq!(vec!("foo", "bar"), split_args("    foo    bar").unwrap());
+        assert_eq!(vec!("foo", "bar"), split_args("foo    bar     ").unwrap());
+        assert_eq!(vec!("foo", "bar"), split_args("    foo    bar     ").unwrap());
     }
     
     #[test]
     fn split_skip_whitespace_characters() {
-        assert_eq!(vec!("foo", "bar"), split_args("foo\t \tbar"));
+        assert_eq!(vec!("foo", "bar"), split_args("foo\t \tbar").unwrap());
     }
     
+    #[test]
+    fn quote_args_with_whitespace() {
+        assert_eq!("Spawn", quote_arg("Spawn"));
+        assert_eq!("\"kind:function prototype\"", quote_arg("kind:function prototype"));
+        assert_eq!(vec!("find", "kind:function prototype", ""),
+                   split_args(&format!("find {} {}", quote_arg("kind:function prototype"), quote_arg(""))).unwrap());
+    }
+
+    #[test]
+    fn quote_args_with_quotes_and_backslashes() {
+        assert_eq!("\"#include \\\"Foo.h\\\"\"", quote_arg("#include \"Foo.h\""));
+        assert_eq!("D:\\Source\\Test.h", quote_arg("D:\\Source\\Test.h"));
+        for arg in &["#include \"Foo.h\"", "\"", "a\\\"b", "D:\\My Source\\", "\\\\"] {
+            assert_eq!(vec!("resolve", *arg), split_args(&format!("resolve {}", quote_arg(arg))).unwrap());
+        }
+    }
+
+    #[test]
+    fn split_non_ascii_words() {
+        assert_eq!(vec!("echo", "héllo", "wörld"), split_args("echo héllo wörld").unwrap());
+        assert_eq!(vec!("echo", "ÄÖ ü", "ß"), split_args("echo\u{3000}\"ÄÖ ü\" ß").unwrap());
+    }
+
+    #[test]
+    fn split_fail_on_unbalanced_quotes() {
+        assert!(split_args("echo \"").is_err());
+        assert!(split_args("echo \"ÄÖ").is_err());
+    }
+
+    #[test]
+    fn split_unescape_quoted_quotes_and_backslashes() {
+        assert_eq!(vec!("resolve", "#include \"Foo.h\""), split_args("resolve \"#include \\\"Foo.h\\\"\"").unwrap());
+        assert_eq!(vec!("D:\\Source\\Test.h", "D:\\Source"), split_args("D:\\Source\\Test.h \"D:\\Source\"").unwrap());
+    }
+
     #[test]
     fn split_preserve_quotes() {
-        assert_eq!(vec!("foo", "bar is quoted"), split_args("foo   \"bar is quoted\"  "));
+        assert_eq!(vec!("foo", "bar is quoted"), split_args("foo   \"bar is quoted\"  ").unwrap());
         assert_eq!(vec!("foo is quoted", "bar is quoted"),
-                   split_args("\"foo is quoted\"   \"bar is quoted\"  "));
+                   split_args("\"foo is quoted\"   \"bar is quoted\"  ").unwrap());
     }
 }
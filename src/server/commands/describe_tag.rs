@@ -1,67 +1,101 @@
-use getopts::Options;
-
 use tags::TagDatabase;
+use tags::TagDefinition;
 use tags::tag_kind_from_str;
 use server::ServerCommand;
-use server::commands::args::split_args;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use server::commands::find_tags::{suggestions_reply, DEFAULT_SUGGESTIONS};
 
 pub struct DescribeTagCommand {
-    options: Options
+    spec: CommandSpec,
 }
 
 impl DescribeTagCommand {
     pub fn new() -> DescribeTagCommand {
-        let mut options = Options::new();
-        options.optopt("k", "kind", "tag kind", "NAME");
-        
         DescribeTagCommand {
-            options,
+            spec: CommandSpec::new("describe", "Describe the tag of a kind and name")
+                .alias("describe-tag")
+                .flag("r", "references", "include reference tags")
+                .option("s", "suggestions", "COUNT", "suggest up to COUNT similar names when nothing matches")
+                .option("f", "from", "FILE", "prefer tags near FILE")
+                .required_option("k", "kind", "KIND", "tag kind")
+                .argument("tag_name"),
         }
     }
 
-    fn do_execute(&self, tag_name: &str, tag_kind: &str, tag_database: &TagDatabase) -> String {
+    /// The most preferred tag of the kind and name, the first one loaded among equals.
+    fn do_execute<'a>(&self,
+                      tag_name: &str,
+                      tag_kind: &str,
+                      preference: &dyn Fn(&TagDefinition) -> i64,
+                      all_tags: Box<dyn Iterator<Item=&'a TagDefinition> + 'a>) -> Reply {
         let kind = tag_kind_from_str(tag_kind);
-        
-        for tag in tag_database.all_tags() {
+
+        let mut best: Option<(i64, &TagDefinition)> = None;
+        for tag in all_tags {
             if tag.kind() == kind && tag.name() == tag_name {
-                return tag.to_elisp()
+                let tag_preference = preference(tag);
+                if best.is_none() || best.unwrap().0 < tag_preference {
+                    best = Some((tag_preference, tag));
+                }
             }
         }
-        
-        "nil".to_string()
-    }
 
-    fn usage_help(&self) -> String {
-        "describe -k <tag_kind> <tag_name>".to_string()
+        match best {
+            None => Reply::Nil,
+            Some((_, tag)) => Reply::tag(tag),
+        }
     }
 }
 
 impl ServerCommand for DescribeTagCommand {
-    fn can_execute(&self, command: &str) -> bool {
-        command.starts_with("describe ")
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
     }
 
-    fn execute(&self, command: &str, tag_database: &mut TagDatabase) -> String {
-        let tokens: Vec<&str> = command.lines().nth(0).unwrap().split(" ").collect();
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let tag_name = args.arg(0);
+        let suggestions = args.parsed_value::<usize>("suggestions")?.unwrap_or(DEFAULT_SUGGESTIONS);
 
-        let args = split_args(command);
-        let tag_name = args.last().unwrap().clone();
-        let matches = match self.options.parse(args) {
-            Ok(m) => m,
-            Err(f) => return f.to_string()
+        let all_tags = if args.flag("references") {
+            tag_database.all_tags()
+        } else {
+            tag_database.all_definitions()
         };
-
-        if matches.opt_present("kind") {
-            return self.do_execute(tag_name, &matches.opt_str("kind").unwrap(), tag_database);
+        let from_file = args.value("from");
+        let preference = tag_database.preference(from_file.as_deref());
+        let reply = self.do_execute(tag_name, &args.value("kind").unwrap_or_default(), &preference, all_tags);
+        if reply != Reply::Nil || tag_database.has_name(tag_name) {
+            return Ok(reply);
         }
 
-        return self.usage_help()
+        Ok(suggestions_reply(tag_database, tag_name, suggestions))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+    use tags::TagFile;
+
+    #[test]
+    fn prefers_higher_priority_tag_files() {
+        let command = DescribeTagCommand::new();
+
+        let mut tag_database = TagDatabase::new();
+        for (priority, path) in [(0, "Engine/Source/Runtime/Engine/Classes/Actor.h"), (10, "Game/Source/Actor.h")].iter() {
+            let mut tag_file = TagFile::new();
+            tag_file.priority = *priority;
+            tag_file.tags.push(TagDefinition::from_string(format!("AActor\t{}\t/^class AActor$/;\"\tc\tline:4", path)));
+            tag_database.add_tag_file(tag_file);
+        }
+
+        let expected = Reply::tag(&tag_database.tag_files[1].tags[0]);
+        assert_eq!(expected, command.execute("describe -k class AActor", &mut tag_database).unwrap());
+        assert_eq!(expected, command.execute("describe -f Engine/Source/Runtime/Engine/Private/Actor.cpp -k class AActor", &mut tag_database).unwrap());
+    }
 
     #[test]
     fn parse_args() {
@@ -1,26 +1,29 @@
 use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
 use tags::TagDatabase;
 
-pub struct EchoCommand;
+pub struct EchoCommand {
+    spec: CommandSpec,
+}
 
 impl EchoCommand {
     pub fn new() -> EchoCommand {
-        EchoCommand {}
+        EchoCommand {
+            spec: CommandSpec::new("echo", "Reply with the arguments").variadic_argument("text"),
+        }
     }
 }
 
 impl ServerCommand for EchoCommand {
-    fn can_execute(&self, command: &str) -> bool {
-        return command.starts_with("echo ");
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
     }
 
-    fn execute(&self, command: &str, _tag_database: &mut TagDatabase) -> String {
-        let arg_separator = command.find(" ");
-        let args = match arg_separator {
-            None => "",
-            Some(v) => &command[v + 1..]
-        };
-        return String::from(args);
+    fn run(&self, args: &CommandArgs, _tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        Ok(Reply::string(args.args().join(" ")))
     }
 }
 
@@ -1,32 +1,40 @@
 use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
 
 use tags::TagDatabase;
-use std::collections::HashMap;
 use std::path::Path;
 
+/// The extensions of the counterparts of files with each extension.
+const OTHER_FILE_EXTENSIONS: [(&str, &[&str]); 4] = [
+    ("cpp", &["h", "hpp"]),
+    ("c", &["h", "hpp"]),
+    ("h", &["c", "cpp"]),
+    ("hpp", &["c", "cpp"]),
+];
+
 pub struct FindOtherFileCommand {
-    extensions: HashMap<&'static str, Vec<&'static str>>,
+    spec: CommandSpec,
 }
 
 impl FindOtherFileCommand {
     pub fn new() -> FindOtherFileCommand {
         FindOtherFileCommand {
-            extensions: [
-                ("cpp", vec!["h", "hpp"]),
-                ("c", vec!["h", "hpp"]),
-                ("h", vec!["c", "cpp"]),
-                ("hpp", vec!["c", "cpp"]),
-            ].iter().cloned().collect(),
+            spec: CommandSpec::new("find-other-file", "Find the header or source file of the same name")
+                .alias("other-file")
+                .argument("file"),
         }
     }
 
-    pub fn match_other_file(other_file_name: &str, file_stem: &str, extensions: &Vec<&str>) -> bool {
+    pub fn match_other_file(other_file_name: &str, file_stem: &str, extensions: &[&str]) -> bool {
         let path = Path::new(other_file_name);
 
         let file_extension_opt = path.extension();
         let extension_matches = match file_extension_opt {
             None => false,
-            Some(v) => extensions.contains(&v.to_str().unwrap()),
+            Some(v) => v.to_str().is_some_and(|v| extensions.contains(&v)),
         };
 
         let file_stem_opt = path.file_stem();
@@ -35,38 +43,48 @@ impl FindOtherFileCommand {
             Some(v) => v == file_stem,
         };
 
-        return stem_matches && extension_matches;
+        stem_matches && extension_matches
     }
 }
 
-impl ServerCommand for FindOtherFileCommand {
-    fn can_execute(&self, command: &str) -> bool {
-        return command.starts_with("find-other-file ");
-    }
-
-    fn execute(&self, command: &str, tag_database: &mut TagDatabase) -> String {
-        let tokens: Vec<&str> = command.lines().nth(0).unwrap().split(" ").collect();
+fn other_file_extensions(extension: &str) -> Option<&'static [&'static str]> {
+    OTHER_FILE_EXTENSIONS.iter().find(|v| v.0 == extension).map(|v| v.1)
+}
 
-        let file_path = tokens[1].trim();
-        let arg_path = Path::new(file_path);
+/// Whether `other_file_path` is the header or source counterpart of `file_path`.
+pub fn is_other_file(file_path: &str, other_file_path: &str) -> bool {
+    let path = Path::new(file_path);
+    let file_stem = match path.file_stem().and_then(|v| v.to_str()) {
+        None => return false,
+        Some(v) => v,
+    };
+
+    match path.extension().and_then(|v| v.to_str()).and_then(other_file_extensions) {
+        None => false,
+        Some(extensions) => FindOtherFileCommand::match_other_file(other_file_path, file_stem, extensions),
+    }
+}
 
-        let file_extension_opt = arg_path.extension();
-        let file_extension = match file_extension_opt {
-            None => return String::from(""),
-            Some(v) => v.to_str().unwrap(),
-        };
+impl ServerCommand for FindOtherFileCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
 
-        let file_name = arg_path.file_stem().unwrap().to_str().unwrap();
-        let other_file_extensions = self.extensions.get(file_extension).unwrap();
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        Ok(Reply::from(other_file(tag_database, args.arg(0))))
+    }
+}
 
-        for tag in tag_database.tag_files.iter().map(|v| &v.tags).flat_map(|v| v) {
-            if FindOtherFileCommand::match_other_file(tag.name(), file_name, other_file_extensions) {
-                return format!("{}", tag.source_file());
-            }
-        }
+/// The path of the header or source counterpart of `file_path`, from the file tags.
+pub fn other_file<'a>(tag_database: &'a TagDatabase, file_path: &str) -> Option<&'a str> {
+    let path = Path::new(file_path);
+    let file_extension = path.extension().and_then(|v| v.to_str())?;
+    let file_name = path.file_stem().and_then(|v| v.to_str()).unwrap_or("");
 
-        String::from(format!("Other file not found."))
-    }
+    let other_file_extensions = other_file_extensions(file_extension)?;
+    tag_database.all_tags()
+        .find(|v| FindOtherFileCommand::match_other_file(v.name(), file_name, other_file_extensions))
+        .map(|v| v.source_file())
 }
 
 #[cfg(test)]
@@ -81,12 +99,12 @@ mod tests {
         tag_file.tags.push(TagDefinition::from_string(file_tag_declaration("/private/Test.cpp")));
         
         let mut tag_database = TagDatabase::new();
-        tag_database.tag_files.push(tag_file);
+        tag_database.add_tag_file(tag_file);
 
         let command = FindOtherFileCommand::new();
 
-        assert_eq!("/private/Test.cpp", command.execute("find-other-file Test.h", &mut tag_database));
-        assert_eq!("/classes/Test.h", command.execute("find-other-file Test.cpp", &mut tag_database));
+        assert_eq!(Reply::string("/private/Test.cpp"), command.execute("find-other-file Test.h", &mut tag_database).unwrap());
+        assert_eq!(Reply::string("/classes/Test.h"), command.execute("find-other-file Test.cpp", &mut tag_database).unwrap());
     }
 
     #[test]
@@ -98,12 +116,12 @@ mod tests {
         tag_file.tags.push(TagDefinition::from_string(file_tag_declaration("/ma/sogetsu/TestA.cpp")));
         
         let mut tag_database = TagDatabase::new();
-        tag_database.tag_files.push(tag_file);
+        tag_database.add_tag_file(tag_file);
         
         let command = FindOtherFileCommand::new();
         
-        assert_eq!("/ma/sogetsu/TestA.cpp", command.execute("find-other-file TestA.h", &mut tag_database));
-        assert_eq!("/1/2/Test.h", command.execute("find-other-file /x/y/Test.cpp", &mut tag_database));
+        assert_eq!(Reply::string("/ma/sogetsu/TestA.cpp"), command.execute("find-other-file TestA.h", &mut tag_database).unwrap());
+        assert_eq!(Reply::string("/1/2/Test.h"), command.execute("find-other-file /x/y/Test.cpp", &mut tag_database).unwrap());
     }
 
     fn file_tag_declaration(file_path: &str) -> String {
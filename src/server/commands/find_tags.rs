@@ -1,71 +1,383 @@
-use getopts::Options;
+use std::cmp::Reverse;
+use std::time::Duration;
+use std::time::Instant;
+
+use regex::Regex;
+use regex::RegexBuilder;
+
+use query::Query;
+use query::QueryMatch;
 
 use tags::TagDatabase;
 use tags::TagDefinition;
-use tags::tag_kind_from_str;
-
-use search::match_abbrev;
+use tags::trigram_index::regex_literals;
 
 use server::ServerCommand;
-use server::commands::args::split_args;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::commands::visited::client_name;
+use server::error::CommandError;
+use server::reply::Reply;
 
 pub struct FindTagsCommand {
-    options: Options
+    spec: CommandSpec,
 }
 
 impl FindTagsCommand {
     pub fn new() -> FindTagsCommand {
-        let mut options = Options::new();
-        
         FindTagsCommand {
-            options,
+            spec: CommandSpec::new("find", "Find tags by fuzzy query, best matches first")
+                .flag("r", "references", "include reference tags")
+                .flag("m", "match-ranges", "reply with the matched character ranges of each name")
+                .option("n", "limit", "COUNT", "reply with a list of up to COUNT tags")
+                .option("s", "suggestions", "COUNT", "suggest up to COUNT similar names when nothing matches")
+                .option("f", "from", "FILE", "prefer tags near FILE among equally good matches")
+                .option("c", "client", "NAME", "rank the tags the client NAME visited higher rather than those of the default one")
+                .option("", "regex", "PATTERN", "match tags by regular expression")
+                .option("", "in", "FIELD", "match the regex against the name, declaration or signature")
+                .variadic_argument("query"),
         }
     }
 
     fn do_execute<'a>(&self,
-                  tag_name: &str,
-                  all_tags: Box<Iterator<Item=&TagDefinition> + 'a>) -> String {
-        for tag in all_tags {
-            if (tag.name().starts_with(tag_name)) {
-                return tag.to_elisp()
+                      query: &Query,
+                      limit: Option<usize>,
+                      match_ranges: bool,
+                      frecency: &dyn Fn(&TagDefinition) -> usize,
+                      preference: &dyn Fn(&TagDefinition) -> i64,
+                      all_tags: Box<dyn Iterator<Item=&'a TagDefinition> + 'a>) -> Reply {
+        let ranked_tags = rank_tags(query, frecency, preference, all_tags);
+        let result_reply = |&(tag, ref query_match): &(&TagDefinition, QueryMatch)| {
+            if !match_ranges {
+                return Reply::tag(tag);
+            }
+            let ranges = query_match.ranges.iter().map(|v| Reply::list(vec![v.0.into(), v.1.into()]));
+            Reply::record("match").with("ranges", Reply::list(ranges)).with("tag", Reply::tag(tag))
+        };
+
+        match limit {
+            None => match ranked_tags.first() {
+                None => Reply::Nil,
+                Some(v) => result_reply(v),
+            },
+            Some(limit) => {
+                if ranked_tags.is_empty() || limit == 0 {
+                    return Reply::Nil;
+                }
+                Reply::list(ranked_tags.iter().take(limit).map(result_reply))
             }
         }
-        
-        "nil".to_string()
     }
 }
 
+/// The text of a tag a regex search matches.
+#[derive(Clone, Copy, PartialEq)]
+pub enum RegexField {
+    Name,
+    Declaration,
+    Signature,
+}
+
+/// Regex searches reply with at most this many tags unless asked otherwise.
+pub const DEFAULT_REGEX_LIMIT: usize = 100;
+
+/// How long a regex search may scan tags before replying with what it found so far.
+pub const REGEX_TIME_BUDGET: Duration = Duration::from_millis(250);
+
+/// Compiled regexes larger than this are rejected rather than built.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// The regex for `pattern`, or a parse error if it is invalid or too large.
+pub fn build_regex(pattern: &str) -> Result<Regex, CommandError> {
+    RegexBuilder::new(pattern).size_limit(REGEX_SIZE_LIMIT).build()
+        .map_err(|e| CommandError::Parse(format!("Invalid regex: {}", e)))
+}
+
+/// The tags whose `field` matches `regex` and which pass `filter`, in the order of the tags
+/// files. Names and indexed declarations are narrowed down to candidates by the literals the
+/// regex requires. The search stops at `limit` tags or once it has run for `time_budget`, in
+/// which case the tags are flagged as truncated.
+pub fn find_by_regex<'a>(tag_database: &'a TagDatabase,
+                         regex: &Regex,
+                         field: RegexField,
+                         include_references: bool,
+                         filter: &dyn Fn(&TagDefinition) -> bool,
+                         limit: usize,
+                         time_budget: Duration) -> (Vec<&'a TagDefinition>, bool) {
+    let candidates = match field {
+        RegexField::Signature => tag_database.all_tags(),
+        _ => tag_database.substring_candidates(&regex_literals(regex.as_str()), field == RegexField::Declaration),
+    };
+
+    let start = Instant::now();
+    let mut result = Vec::new();
+    for (i, tag) in candidates.enumerate() {
+        if result.len() >= limit {
+            break;
+        }
+        if i % 1024 == 1023 && start.elapsed() > time_budget {
+            warn!("Regex search for \"{}\" stopped after {} tags", regex.as_str(), i);
+            return (result, true);
+        }
+        if !include_references && tag.is_reference() {
+            continue;
+        }
+
+        let text = match field {
+            RegexField::Name => Some(tag.name()),
+            RegexField::Declaration => Some(tag.declaration()),
+            RegexField::Signature => tag.signature(),
+        };
+        if text.is_some_and(|v| regex.is_match(v)) && filter(tag) {
+            result.push(tag);
+        }
+    }
+    (result, false)
+}
+
+/// The reply of a regex search: the tags, or for a search cut short by its time budget a
+/// `truncated` record holding the tags found so far.
+pub fn regex_reply(tags: &[&TagDefinition], truncated: bool) -> Reply {
+    if !truncated {
+        return Reply::tags(tags);
+    }
+    Reply::record("truncated")
+        .with("tags", Reply::tags(tags))
+        .with("message", format!("The search stopped after {} ms, more tags may match", REGEX_TIME_BUDGET.as_millis()))
+}
+
+pub const DEFAULT_SUGGESTIONS: usize = 5;
+
+/// The reply for a lookup of `name` which found nothing: the names it may have been a typo of,
+/// marked as suggestions, or nil.
+pub fn suggestions_reply(tag_database: &TagDatabase, name: &str, limit: usize) -> Reply {
+    let suggestions = tag_database.suggest_names(name, limit);
+    if suggestions.is_empty() {
+        return Reply::Nil;
+    }
+
+    Reply::record("suggestions").with("names", Reply::list(suggestions.into_iter().map(Reply::from)))
+}
+
+/// The tags matching the query with how they matched, best first. Match scores are boosted by
+/// how often and how recently tags were visited. Equally good matches are ordered by
+/// preference, then keep the order of the tags files.
+pub fn rank_tags<'a>(query: &Query,
+                 frecency: &dyn Fn(&TagDefinition) -> usize,
+                 preference: &dyn Fn(&TagDefinition) -> i64,
+                 all_tags: Box<dyn Iterator<Item=&'a TagDefinition> + 'a>) -> Vec<(&'a TagDefinition, QueryMatch)> {
+    let mut scored_tags: Vec<_> = all_tags
+        .filter_map(|v| query.match_tag(v).map(|query_match| (v, query_match)))
+        .map(|v| ((v.1.score + frecency(v.0), preference(v.0)), v))
+        .collect();
+    scored_tags.sort_by_key(|v| Reverse(v.0));
+    scored_tags.into_iter().map(|v| v.1).collect()
+}
+
 impl ServerCommand for FindTagsCommand {
-    fn can_execute(&self, command: &str) -> bool {
-        command.starts_with("find ")
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
     }
 
-    fn execute(&self, command: &str, tag_database: &mut TagDatabase) -> String {
-        let tokens: Vec<&str> = command.lines().nth(0).unwrap().split(" ").collect();
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let limit = args.parsed_value::<usize>("limit")?;
+
+        let terms = args.args();
+        if let Some(pattern) = args.value("regex") {
+            let field = match args.value("in").as_ref().map(|v| &v[..]) {
+                None | Some("name") => RegexField::Name,
+                Some("declaration") => RegexField::Declaration,
+                Some("signature") => RegexField::Signature,
+                Some(_) => return Err(args.usage_error()),
+            };
+            let regex = build_regex(&pattern)?;
+            let query = if terms.is_empty() {
+                None
+            } else {
+                Some(Query::parse(&terms).map_err(CommandError::Parse)?)
+            };
+
+            let filter = |tag: &TagDefinition| query.as_ref().is_none_or(|v| v.matches(tag));
+            let limit = limit.unwrap_or(DEFAULT_REGEX_LIMIT);
+            let (tags, truncated) = find_by_regex(tag_database, &regex, field, args.flag("references"), &filter, limit, REGEX_TIME_BUDGET);
+            return Ok(regex_reply(&tags, truncated));
+        }
+
+        let query = Query::parse(&terms).map_err(CommandError::Parse)?;
 
-        let args = split_args(command);
-        let tag_name = args.last().unwrap();
+        let suggestions = args.parsed_value::<usize>("suggestions")?.unwrap_or(DEFAULT_SUGGESTIONS);
 
-        return self.do_execute(tag_name,
-                               tag_database.all_tags());
+        let match_ranges = args.flag("match-ranges");
+        let from_file = args.value("from");
+        let client = client_name(args)?;
+        let frecency = |tag: &TagDefinition| tag_database.frecency(&client, tag);
+        let preference = tag_database.preference(from_file.as_deref());
+        let reply = if args.flag("references") {
+            self.do_execute(&query, limit, match_ranges, &frecency, &preference, tag_database.all_tags())
+        } else {
+            self.do_execute(&query, limit, match_ranges, &frecency, &preference, tag_database.all_definitions())
+        };
+
+        if reply != Reply::Nil {
+            return Ok(reply);
+        }
+
+        // a name only found among the tags the other terms rejected is no typo
+        let name_found = query.name_query().is_some_and(|v| tag_database.all_tags().any(|tag| v.matches(tag)));
+        Ok(match query.name_term() {
+            Some(name) if !name_found => suggestions_reply(tag_database, name, suggestions),
+            _ => Reply::Nil,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tags::TagDefinition;
-    
+    use tags::TagFile;
+    use tags::visit_history::DEFAULT_CLIENT;
+
     #[test]
     fn finds_one_tag() {
         let command = FindTagsCommand::new();
 
-        let tag_str = "Test	test/Test.h	/^class Test {$/;\"	c\tline:10".to_string();
-        
         let tags = vec!(
             TagDefinition::from_string("DoTest\ttest/Test.h\t/^	int DoTest() const {}$/;\"\tf\tclass:Test	typeref:typename:int".to_string()),
             TagDefinition::from_string("Test\ttest/Test.h\t/^class Test {$/;\"\tc\tline:10".to_string())
         );
-        assert_eq!(tags.first().unwrap().to_elisp(), command.do_execute("DoTest", Box::new(tags.iter())));
+        let query = Query::parse(&["DoTest"]).unwrap();
+        assert_eq!(Reply::tag(tags.first().unwrap()), command.do_execute(&query, None, false, &|_| 0, &|_| 0, Box::new(tags.iter())));
+    }
+
+    #[test]
+    fn skips_reference_tags_by_default() {
+        let command = FindTagsCommand::new();
+
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("Test.h\tMain.cpp\t/^#include \"Test.h\"$/;\"\th\tline:1\troles:local".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("Test.h\tTest.h\t1;\"\tF\tline:1".to_string()));
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+
+        let reference = Reply::tag(&tag_database.tag_files[0].tags[0]);
+        let definition = Reply::tag(&tag_database.tag_files[0].tags[1]);
+        assert_eq!(definition, command.execute("find Test.h", &mut tag_database).unwrap());
+        assert_eq!(reference, command.execute("find -r Test.h", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn ranks_filtered_tags() {
+        let command = FindTagsCommand::new();
+
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("ATestActor\tTest.h\t/^class ATestActor$/;\"\tc\tline:3".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("ATest\tTest.h\t/^struct ATest$/;\"\ts\tline:8".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("ATest\tTest.cpp\t/^ATest::ATest()$/;\"\tf\tline:8\tclass:ATest".to_string()));
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+
+        let expected = {
+            let tags = &tag_database.tag_files[0].tags;
+            Reply::tags(&[&tags[1], &tags[0]])
+        };
+        assert_eq!(expected, command.execute("find -n 5 ATest kind:class|struct", &mut tag_database).unwrap());
+        assert_eq!(Err(CommandError::Parse("Unknown tag kind 'bogus'".to_string())), command.execute("find ATest kind:bogus", &mut tag_database));
+    }
+
+    #[test]
+    fn suggests_names_when_nothing_matches() {
+        let command = FindTagsCommand::new();
+
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("SpawnActor\tWorld.h\t/^\tAActor* SpawnActor();$/;\"\tp\tline:3".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("SpawnActors\tWorld.h\t/^\tvoid SpawnActors();$/;\"\tp\tline:4".to_string()));
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+
+        assert_eq!("(suggestions :names (\"SpawnActor\" \"SpawnActors\"))", command.execute("find SpwanActor", &mut tag_database).unwrap().to_elisp());
+        assert_eq!("(suggestions :names (\"SpawnActor\"))", command.execute("find -s 1 SpwanActor", &mut tag_database).unwrap().to_elisp());
+        assert_eq!(Reply::Nil, command.execute("find Render", &mut tag_database).unwrap());
+        assert_eq!(Reply::Nil, command.execute("find SpawnActor kind:class", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn finds_tags_by_regex() {
+        let command = FindTagsCommand::new();
+
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("GetRootComponent\tActor.h\t/^\tUSceneComponent* GetRootComponent() const;$/;\"\tp\tline:3\tclass:AActor\tsignature:() const".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("RootComponent\tActor.h\t/^\tUPROPERTY() USceneComponent* RootComponent;$/;\"\tm\tline:5\tclass:AActor".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("GetComponents\tActor.h\t/^\tvoid GetComponents(TArray<UActorComponent*>& OutComponents);$/;\"\tp\tline:7\tclass:AActor\tsignature:(TArray<UActorComponent*>& OutComponents)".to_string()));
+        let mut tag_database = TagDatabase::new();
+        tag_database.set_index_declarations(true);
+        tag_database.add_tag_file(tag_file);
+
+        let (get_root, root, get_components) = {
+            let tags = &tag_database.tag_files[0].tags;
+            (Reply::tags(&[&tags[0]]), Reply::tags(&[&tags[1]]), Reply::tags(&[&tags[2]]))
+        };
+        assert_eq!(get_root, command.execute("find --regex ^Get.*Component$", &mut tag_database).unwrap());
+        assert_eq!(root, command.execute("find --regex UPROPERTY --in declaration", &mut tag_database).unwrap());
+        assert_eq!(get_components, command.execute("find --regex TArray --in signature", &mut tag_database).unwrap());
+        assert_eq!(get_root, command.execute("find --regex Component -n 1", &mut tag_database).unwrap());
+        assert_eq!(root, command.execute("find --regex Component kind:member", &mut tag_database).unwrap());
+        assert_eq!("parse", command.execute("find --regex Get( ", &mut tag_database).unwrap_err().kind());
+    }
+
+    #[test]
+    fn flags_regex_searches_stopped_by_the_time_budget() {
+        let mut tag_file = TagFile::new();
+        for i in 0..2000 {
+            tag_file.tags.push(TagDefinition::from_string(format!("Spawn{}\tWorld.h\t/^\tvoid Spawn{}();$/;\"\tp\tline:{}", i, i, i + 1)));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+
+        let regex = build_regex("^Spawn").unwrap();
+        let (tags, truncated) = find_by_regex(&tag_database, &regex, RegexField::Name, false, &|_| true, 5000, Duration::from_millis(0));
+        assert!(truncated);
+        assert_eq!(1023, tags.len());
+        let (tags, truncated) = find_by_regex(&tag_database, &regex, RegexField::Name, false, &|_| true, 5000, REGEX_TIME_BUDGET);
+        assert!(!truncated);
+        assert_eq!(2000, tags.len());
+        assert!(regex_reply(&tags[..1], true).to_elisp().starts_with("(truncated :tags ((tag :name \"Spawn0\""));
+    }
+
+    #[test]
+    fn ranks_visited_tags_higher() {
+        let command = FindTagsCommand::new();
+
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("SpawnActor\tWorld.h\t/^\tAActor* SpawnActor();$/;\"\tp\tline:3".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("SpawnActors\tSpawner.h\t/^\tvoid SpawnActors();$/;\"\tp\tline:4".to_string()));
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+
+        let (spawn_actor, spawn_actors) = {
+            let tags = &tag_database.tag_files[0].tags;
+            (Reply::tag(&tags[0]), Reply::tag(&tags[1]))
+        };
+        assert_eq!(spawn_actor, command.execute("find SpawnAc", &mut tag_database).unwrap());
+        assert_eq!(Ok(true), tag_database.record_visit(DEFAULT_CLIENT, "Spawner.h", 4));
+        assert_eq!(spawn_actors, command.execute("find SpawnAc", &mut tag_database).unwrap());
+        assert_eq!(spawn_actor, command.execute("find SpawnActor", &mut tag_database).unwrap());
+        assert_eq!(spawn_actor, command.execute("find --client emacs SpawnAc", &mut tag_database).unwrap());
+        assert_eq!(Ok(true), tag_database.record_visit("emacs", "World.h", 3));
+        assert_eq!(Ok(true), tag_database.record_visit("emacs", "World.h", 3));
+        assert_eq!(spawn_actor, command.execute("find --client emacs SpawnAc", &mut tag_database).unwrap());
+        assert_eq!(spawn_actors, command.execute("find SpawnAc", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn reports_match_ranges() {
+        let command = FindTagsCommand::new();
+
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("ProjectileSpawnLocation\tTest.h\t/^\tFVector ProjectileSpawnLocation;$/;\"\tm\tline:3".to_string()));
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+
+        let expected = format!("(match :ranges ((0 4) (10 12) (15 18)) :tag {})", Reply::tag(&tag_database.tag_files[0].tags[0]).to_elisp());
+        assert_eq!(expected, command.execute("find -m ProjSpLoc", &mut tag_database).unwrap().to_elisp());
     }
 }
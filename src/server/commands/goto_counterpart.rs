@@ -0,0 +1,215 @@
+use std::cmp::Reverse;
+
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use tags::TagDatabase;
+use tags::TagDefinition;
+use tags::TagKind;
+
+pub struct GotoDefinitionCommand {
+    spec: CommandSpec,
+}
+
+impl GotoDefinitionCommand {
+    pub fn new() -> GotoDefinitionCommand {
+        GotoDefinitionCommand {
+            spec: CommandSpec::new("goto-definition", "Go to the definition of a function from its declaration")
+                .alias("definition")
+                .argument("qualified_name|file")
+                .optional_argument("line"),
+        }
+    }
+}
+
+impl ServerCommand for GotoDefinitionCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        goto_counterpart(args, TagKind::FunctionDefinition, tag_database)
+    }
+}
+
+pub struct GotoDeclarationCommand {
+    spec: CommandSpec,
+}
+
+impl GotoDeclarationCommand {
+    pub fn new() -> GotoDeclarationCommand {
+        GotoDeclarationCommand {
+            spec: CommandSpec::new("goto-declaration", "Go to the declaration of a function from its definition")
+                .alias("declaration")
+                .argument("qualified_name|file")
+                .optional_argument("line"),
+        }
+    }
+}
+
+impl ServerCommand for GotoDeclarationCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        goto_counterpart(args, TagKind::FunctionPrototype, tag_database)
+    }
+}
+
+/// Replies with the single counterpart when it can be told apart by scope and signature, and
+/// with a ranked list of candidates otherwise. The counterpart is of a qualified name, or of
+/// the function at a line of a file.
+fn goto_counterpart(args: &CommandArgs, target_kind: TagKind, tag_database: &TagDatabase) -> Result<Reply, CommandError> {
+    let candidates = match args.optional_arg(1) {
+        None => find_by_name(args.arg(0), &target_kind, tag_database),
+        Some(_) => {
+            let line = args.parsed_arg::<usize>(1)?;
+            match find_source_tag(args.arg(0), line, &target_kind, tag_database).map_err(CommandError::Usage)? {
+                None => Vec::new(),
+                Some(source) => rank_counterparts(source, &target_kind, tag_database),
+            }
+        }
+    };
+
+    let exact_matches: Vec<&(usize, &TagDefinition)> = candidates.iter()
+        .filter(|v| v.0 == EXACT_MATCH_SCORE).collect();
+    if exact_matches.len() == 1 {
+        return Ok(Reply::tag(exact_matches[0].1));
+    }
+
+    let ranked_tags: Vec<&TagDefinition> = candidates.iter().map(|v| v.1).collect();
+    Ok(Reply::tags(&ranked_tags))
+}
+
+const EXACT_MATCH_SCORE: usize = 3;
+
+fn find_by_name<'a>(qualified_name: &str,
+                    target_kind: &TagKind,
+                    tag_database: &'a TagDatabase) -> Vec<(usize, &'a TagDefinition)> {
+    let is_qualified = qualified_name.contains("::");
+    let candidates: Vec<&TagDefinition> = tag_database.all_tags()
+        .filter(|v| v.kind() == *target_kind)
+        .filter(|v| if is_qualified { v.qualified_name() == qualified_name } else { v.name() == qualified_name })
+        .collect();
+
+    // without a source tag there is no signature to tell overloads apart
+    let score = if candidates.len() == 1 { EXACT_MATCH_SCORE } else { 0 };
+    candidates.into_iter().map(|v| (score, v)).collect()
+}
+
+/// The function whose declaration or body contains `line`, unless it is already of the kind
+/// looked for. Fails when `file_path` matches several files.
+fn find_source_tag<'a>(file_path: &str,
+                       line: usize,
+                       target_kind: &TagKind,
+                       tag_database: &'a TagDatabase) -> Result<Option<&'a TagDefinition>, String> {
+    Ok(tag_database.scope_at(file_path, line)?
+        .map(|(tag, _, _)| tag)
+        .filter(|v| v.is_method() && v.kind() != *target_kind))
+}
+
+fn rank_counterparts<'a>(source: &TagDefinition,
+                         target_kind: &TagKind,
+                         tag_database: &'a TagDatabase) -> Vec<(usize, &'a TagDefinition)> {
+    let source_signature = source.normalized_signature();
+
+    let mut candidates: Vec<(usize, &TagDefinition)> = tag_database.all_tags()
+        .filter(|v| v.kind() == *target_kind && v.name() == source.name())
+        .map(|v| {
+            let mut score = 1;
+            if v.scope_name() == source.scope_name() {
+                score += 1;
+            }
+            if v.normalized_signature() == source_signature {
+                score += 1;
+            }
+            (score, v)
+        })
+        .collect();
+
+    candidates.sort_by_key(|v| Reverse(v.0));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Actor.h\tActor.h\t1;\"\tF\tline:1",
+            "Actor.cpp\tActor.cpp\t1;\"\tF\tline:1",
+            "Spawn\tActor.h\t/^\tvoid Spawn(int Count);$/;\"\tp\tline:10\tclass:AActor\tsignature:(int Count)",
+            "Spawn\tActor.h\t/^\tvoid Spawn(float Delay);$/;\"\tp\tline:11\tclass:AActor\tsignature:(float Delay)",
+            "Spawn\tActor.cpp\t/^void AActor::Spawn(int InCount)$/;\"\tf\tline:20\tclass:AActor\tsignature:(int InCount)\tend:25",
+            "Spawn\tActor.cpp\t/^void AActor::Spawn(float InDelay)$/;\"\tf\tline:30\tclass:AActor\tsignature:(float InDelay)",
+            "Tick\tActor.h\t/^\tvoid Tick();$/;\"\tp\tline:12\tclass:AActor\tsignature:()",
+            "Tick\tActor.cpp\t/^void AActor::Tick()$/;\"\tf\tline:40\tclass:AActor\tsignature:()",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    fn tag_at<'a>(tag_database: &'a TagDatabase, file: &str, line: usize) -> &'a TagDefinition {
+        tag_database.all_tags().find(|v| v.source_file() == file && v.source_line() == line).unwrap()
+    }
+
+    #[test]
+    fn should_pair_overloads_by_signature() {
+        let mut tag_database = test_database();
+        let command = GotoDefinitionCommand::new();
+        let expected = Reply::tag(tag_at(&tag_database, "Actor.cpp", 30));
+        assert_eq!(expected, command.execute("goto-definition /src/Actor.h 11", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn should_find_declaration_from_inside_body() {
+        let mut tag_database = test_database();
+        let command = GotoDeclarationCommand::new();
+        let expected = Reply::tag(tag_at(&tag_database, "Actor.h", 10));
+        assert_eq!(expected, command.execute("goto-declaration Actor.cpp 23", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn should_find_unique_counterpart_by_name() {
+        let mut tag_database = test_database();
+        let command = GotoDefinitionCommand::new();
+        let expected = Reply::tag(tag_at(&tag_database, "Actor.cpp", 40));
+        assert_eq!(expected, command.execute("goto-definition AActor::Tick", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn should_list_ambiguous_overloads() {
+        let mut tag_database = test_database();
+        let command = GotoDefinitionCommand::new();
+        let expected = Reply::tags(&[tag_at(&tag_database, "Actor.cpp", 20), tag_at(&tag_database, "Actor.cpp", 30)]);
+        assert_eq!(expected, command.execute("goto-definition AActor::Spawn", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn should_find_nothing_after_a_function_ends() {
+        let mut tag_database = test_database();
+        let command = GotoDeclarationCommand::new();
+        assert_eq!(Reply::tags(&[]), command.execute("goto-declaration Actor.cpp 27", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn should_report_ambiguous_files() {
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("Actor.cpp\tGame/Actor.cpp\t1;\"\tF\tline:1".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("Actor.cpp\tOther/Actor.cpp\t1;\"\tF\tline:1".to_string()));
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        let command = GotoDeclarationCommand::new();
+        assert_eq!(Err(CommandError::Usage("'Actor.cpp' is ambiguous, it matches Game/Actor.cpp, Other/Actor.cpp".to_string())),
+                   command.execute("goto-declaration Actor.cpp 23", &mut tag_database));
+    }
+}
@@ -0,0 +1,127 @@
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::commands::find_tags::REGEX_TIME_BUDGET;
+use server::commands::find_tags::RegexField;
+use server::commands::find_tags::build_regex;
+use server::commands::find_tags::find_by_regex;
+use server::commands::find_tags::regex_reply;
+use server::reply::Reply;
+use tags::TagDatabase;
+use tags::TagDefinition;
+
+pub struct GrepTagsCommand {
+    spec: CommandSpec,
+}
+
+/// Common substrings match a large part of an engine's tags, so replies are capped unless
+/// asked otherwise.
+pub const DEFAULT_LIMIT: usize = 100;
+
+impl GrepTagsCommand {
+    pub fn new() -> GrepTagsCommand {
+        GrepTagsCommand {
+            spec: CommandSpec::new("grep-tags", "Find tags whose name or declaration contains a substring or regex")
+                .alias("grep")
+                .flag("e", "regex", "match the pattern as a regular expression")
+                .flag("d", "declarations", "match the declaration text instead of the name")
+                .flag("r", "references", "include reference tags")
+                .option("n", "limit", "COUNT", "reply with up to COUNT tags")
+                .argument("pattern"),
+        }
+    }
+}
+
+impl ServerCommand for GrepTagsCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let limit = args.parsed_value::<usize>("limit")?.unwrap_or(DEFAULT_LIMIT);
+
+        let pattern = args.arg(0);
+        let in_declarations = args.flag("declarations");
+        let include_references = args.flag("references");
+
+        if args.flag("regex") {
+            let regex = build_regex(pattern)?;
+            let field = if in_declarations { RegexField::Declaration } else { RegexField::Name };
+            let (tags, truncated) = find_by_regex(tag_database, &regex, field, include_references, &|_| true, limit, REGEX_TIME_BUDGET);
+            return Ok(regex_reply(&tags, truncated));
+        }
+
+        // the index narrows the search down to a few candidates, each then verified in full
+        let substring = pattern.to_lowercase();
+        let tags: Vec<&TagDefinition> = tag_database.substring_candidates(::std::slice::from_ref(&substring), in_declarations)
+            .filter(|v| include_references || !v.is_reference())
+            .filter(|v| text_of(v, in_declarations).to_lowercase().contains(&substring))
+            .take(limit)
+            .collect();
+
+        Ok(Reply::tags(&tags))
+    }
+}
+
+fn text_of(tag: &TagDefinition, in_declarations: bool) -> &str {
+    if in_declarations { tag.declaration() } else { tag.name() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "ProjectileSpawnLocation\tGun.h\t/^\tFVector ProjectileSpawnLocation;$/;\"\tm\tline:3\tclass:AGun",
+            "SpawnActor\tWorld.h\t/^\tAActor* SpawnActor(UClass* Class);$/;\"\tp\tline:12\tclass:UWorld",
+            "GetSpawnLocation\tSpawner.h\t/^\tFVector GetSpawnLocation() const;$/;\"\tp\tline:8\tclass:ASpawner",
+            "GetSpawnLocation\tSpawner.cpp\t/^\treturn GetSpawnLocation();$/;\"\tp\tline:20\troles:call",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.set_index_declarations(true);
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_find_substring_anywhere_in_name() {
+        let mut tag_database = test_database();
+        let command = GrepTagsCommand::new();
+        let expected = {
+            let tags = &tag_database.tag_files[0].tags;
+            Reply::tags(&[&tags[0], &tags[2]])
+        };
+        assert_eq!(expected, command.execute("grep-tags spawnlocation", &mut tag_database).unwrap());
+        assert_eq!(Reply::tags(&[]), command.execute("grep-tags Render", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn should_match_regex() {
+        let mut tag_database = test_database();
+        let command = GrepTagsCommand::new();
+        let expected = Reply::tags(&[&tag_database.tag_files[0].tags[2]]);
+        assert_eq!(expected, command.execute("grep-tags -e ^Get.*Location$", &mut tag_database).unwrap());
+        assert_eq!("parse", command.execute("grep-tags -e Spawn(", &mut tag_database).unwrap_err().kind());
+    }
+
+    #[test]
+    fn should_match_declarations_and_references_on_request() {
+        let mut tag_database = test_database();
+        let command = GrepTagsCommand::new();
+        let expected = Reply::tags(&[&tag_database.tag_files[0].tags[1]]);
+        assert_eq!(expected, command.execute("grep-tags -d UClass*", &mut tag_database).unwrap());
+
+        let expected = {
+            let tags = &tag_database.tag_files[0].tags;
+            Reply::tags(&[&tags[2], &tags[3]])
+        };
+        assert_eq!(expected, command.execute("grep-tags -r -n 5 GetSpawn", &mut tag_database).unwrap());
+        assert_eq!(Err(CommandError::Usage("usage: grep-tags [-e] [-d] [-r] [-n <count>] <pattern>".to_string())), command.execute("grep-tags ", &mut tag_database));
+    }
+}
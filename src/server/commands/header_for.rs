@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use tags::TagDatabase;
+use tags::TagDefinition;
+use tags::TagKind;
+
+pub struct HeaderForCommand {
+    spec: CommandSpec,
+    include_roots: Vec<String>,
+}
+
+impl HeaderForCommand {
+    /// `include_roots` are either directories, whose path prefix is stripped from the header path,
+    /// or bare folder names such as `Public` which are matched anywhere in the header path.
+    pub fn new(include_roots: Vec<String>) -> HeaderForCommand {
+        HeaderForCommand {
+            spec: CommandSpec::new("header-for", "Find the header declaring a symbol and its #include line").argument("symbol"),
+            include_roots,
+        }
+    }
+
+    pub fn default_include_roots() -> Vec<String> {
+        vec!["Public".to_string(), "Classes".to_string()]
+    }
+
+    /// The path to write in the `#include` directive for `header_path`.
+    fn include_path(&self, header_path: &str) -> String {
+        let header_path = header_path.replace('\\', "/");
+
+        let mut best: Option<&str> = None;
+        for root in &self.include_roots {
+            let root = root.replace('\\', "/");
+            let relative = if root.contains('/') {
+                let prefix = format!("{}/", root.trim_end_matches('/'));
+                if header_path.starts_with(&prefix) { Some(&header_path[prefix.len()..]) } else { None }
+            } else {
+                header_path.rfind(&format!("/{}/", root)).map(|v| &header_path[v + root.len() + 2..])
+                    .or_else(|| if header_path.starts_with(&format!("{}/", root)) { Some(&header_path[root.len() + 1..]) } else { None })
+            };
+
+            // the shortest relative path comes from the innermost root
+            if let Some(relative) = relative {
+                if best.is_none() || best.unwrap().len() > relative.len() {
+                    best = Some(relative);
+                }
+            }
+        }
+
+        match best {
+            Some(v) => v.to_string(),
+            None => Path::new(&header_path).file_name().and_then(|v| v.to_str()).unwrap_or("").to_string(),
+        }
+    }
+}
+
+impl ServerCommand for HeaderForCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        Ok(match find_declaring_header(tag_database, args.arg(0)) {
+            None => Reply::Nil,
+            Some(tag) => Reply::record("header")
+                .with("file", tag.source_file())
+                .with("include", format!("#include \"{}\"", self.include_path(tag.source_file())))
+                .with("tag", Reply::tag(tag)),
+        })
+    }
+}
+
+/// The declaration of `symbol` in a header, preferring class and struct declarations, then
+/// function prototypes and other type declarations.
+fn find_declaring_header<'a>(tag_database: &'a TagDatabase, symbol: &str) -> Option<&'a TagDefinition> {
+    let is_qualified = symbol.contains("::");
+
+    let mut best: Option<(usize, &TagDefinition)> = None;
+    for tag in tag_database.all_definitions() {
+        let name_matches = if is_qualified { tag.qualified_name() == symbol } else { tag.name() == symbol };
+        if !name_matches || !is_header(tag.source_file()) {
+            continue;
+        }
+
+        let score = match tag.kind() {
+            TagKind::Class | TagKind::Struct => 3,
+            TagKind::FunctionPrototype | TagKind::Enum | TagKind::Typedef | TagKind::Union => 2,
+            TagKind::ForwardDeclaration | TagKind::File | TagKind::HeaderInclude => continue,
+            _ => 1,
+        };
+        if best.is_none() || best.unwrap().0 < score {
+            best = Some((score, tag));
+        }
+    }
+
+    best.map(|v| v.1)
+}
+
+fn is_header(file_path: &str) -> bool {
+    match Path::new(file_path).extension().and_then(|v| v.to_str()) {
+        Some(extension) => ["h", "hpp", "hh", "hxx"].contains(&extension),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "AActor\tEngine/Source/Runtime/Engine/Private/Actor.cpp\t/^AActor::AActor()$/;\"\tf\tline:20\tclass:AActor",
+            "AActor\tEngine/Source/Runtime/Engine/Classes/GameFramework/Pawn.h\t/^class AActor;$/;\"\tx\tline:8",
+            "AActor\tEngine/Source/Runtime/Engine/Classes/GameFramework/Actor.h\t/^class AActor : public UObject$/;\"\tc\tline:40",
+            "SpawnActor\tEngine/Source/Runtime/Engine/Public/World.h\t/^\tAActor* SpawnActor();$/;\"\tp\tline:12\tclass:UWorld",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_prefer_class_declaration_in_header() {
+        let mut tag_database = test_database();
+        let command = HeaderForCommand::new(HeaderForCommand::default_include_roots());
+        let reply = command.execute("header-for AActor", &mut tag_database).unwrap().to_elisp();
+        assert!(reply.starts_with("(header :file \"Engine/Source/Runtime/Engine/Classes/GameFramework/Actor.h\" \
+                                   :include \"#include \\\"GameFramework/Actor.h\\\"\""));
+    }
+
+    #[test]
+    fn should_strip_configured_root_directories() {
+        let command = HeaderForCommand::new(vec!["Engine/Source/Runtime/".to_string(), "Public".to_string()]);
+        assert_eq!("World.h", command.include_path("Engine/Source/Runtime/Engine/Public/World.h"));
+        assert_eq!("Engine/Classes/Actor.h", command.include_path("Engine/Source/Runtime/Engine/Classes/Actor.h"));
+        assert_eq!("Other.h", command.include_path("/elsewhere/Other.h"));
+    }
+
+    #[test]
+    fn should_reply_nil_for_unknown_symbol() {
+        let mut tag_database = test_database();
+        let command = HeaderForCommand::new(HeaderForCommand::default_include_roots());
+        assert_eq!(Reply::Nil, command.execute("header-for UMissing", &mut tag_database).unwrap());
+    }
+}
@@ -0,0 +1,143 @@
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use tags::TagDatabase;
+
+pub struct IncludesCommand {
+    spec: CommandSpec,
+}
+
+impl IncludesCommand {
+    pub fn new() -> IncludesCommand {
+        IncludesCommand {
+            spec: CommandSpec::new("includes", "List the headers a file includes, directly and transitively").argument("file"),
+        }
+    }
+}
+
+impl ServerCommand for IncludesCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let graph = tag_database.include_graph();
+        Ok(match graph.includes(args.arg(0)).map_err(CommandError::Usage)? {
+            None => Reply::Nil,
+            Some((direct, transitive)) => {
+                let reply = Reply::record("includes").with("direct", files_reply(&direct)).with("transitive", files_reply(&transitive));
+                let ambiguous = graph.ambiguous_includes(args.arg(0)).map_err(CommandError::Usage)?;
+                if ambiguous.is_empty() { reply } else { reply.with("ambiguous", Reply::list(ambiguous.iter().map(|&v| Reply::from(v)))) }
+            }
+        })
+    }
+}
+
+pub struct IncludedByCommand {
+    spec: CommandSpec,
+}
+
+impl IncludedByCommand {
+    pub fn new() -> IncludedByCommand {
+        IncludedByCommand {
+            spec: CommandSpec::new("included-by", "List the files including a header, directly and transitively").argument("file"),
+        }
+    }
+}
+
+impl ServerCommand for IncludedByCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let graph = tag_database.include_graph();
+        Ok(match graph.included_by(args.arg(0)).map_err(CommandError::Usage)? {
+            None => Reply::Nil,
+            Some((direct, transitive)) => {
+                Reply::record("included-by").with("direct", files_reply(&direct)).with("transitive", files_reply(&transitive))
+            }
+        })
+    }
+}
+
+pub struct IncludePathCommand {
+    spec: CommandSpec,
+}
+
+impl IncludePathCommand {
+    pub fn new() -> IncludePathCommand {
+        IncludePathCommand {
+            spec: CommandSpec::new("include-path", "Find a chain of includes from one file to another")
+                .argument("from_file")
+                .argument("to_file"),
+        }
+    }
+}
+
+impl ServerCommand for IncludePathCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let graph = tag_database.include_graph();
+        Ok(match graph.include_path(args.arg(0), args.arg(1)).map_err(CommandError::Usage)? {
+            None => Reply::Nil,
+            Some(path) => files_reply(&path),
+        })
+    }
+}
+
+fn files_reply(files: &[&str]) -> Reply {
+    Reply::list(files.iter().map(|&v| Reply::from(v)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagDefinition;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Main.cpp\tMain.cpp\t1;\"\tF\tline:1",
+            "Actor.h\tActor.h\t1;\"\tF\tline:1",
+            "Object.h\tObject.h\t1;\"\tF\tline:1",
+            "Actor.h\tMain.cpp\t/^#include \"Actor.h\"$/;\"\th\tline:1\troles:local",
+            "Object.h\tActor.h\t/^#include \"Object.h\"$/;\"\th\tline:1\troles:local",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_reply_with_includes() {
+        let mut tag_database = test_database();
+        assert_eq!("(includes :direct (\"Actor.h\") :transitive (\"Actor.h\" \"Object.h\"))",
+                   IncludesCommand::new().execute("includes Main.cpp", &mut tag_database).unwrap().to_elisp());
+    }
+
+    #[test]
+    fn should_reply_with_included_by() {
+        let mut tag_database = test_database();
+        assert_eq!("(included-by :direct (\"Actor.h\") :transitive (\"Actor.h\" \"Main.cpp\"))",
+                   IncludedByCommand::new().execute("included-by Object.h", &mut tag_database).unwrap().to_elisp());
+        assert_eq!("(included-by :direct nil :transitive nil)",
+                   IncludedByCommand::new().execute("included-by Main.cpp", &mut tag_database).unwrap().to_elisp());
+    }
+
+    #[test]
+    fn should_reply_with_include_path() {
+        let mut tag_database = test_database();
+        assert_eq!("(\"Main.cpp\" \"Actor.h\" \"Object.h\")",
+                   IncludePathCommand::new().execute("include-path Main.cpp Object.h", &mut tag_database).unwrap().to_elisp());
+        assert_eq!("nil", IncludePathCommand::new().execute("include-path Object.h Main.cpp", &mut tag_database).unwrap().to_elisp());
+    }
+}
@@ -1,36 +1,64 @@
 use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
 use tags::TagDatabase;
 use tags::TagFile;
 
-pub struct LoadTagsFileCommand {}
+pub struct LoadTagsFileCommand {
+    spec: CommandSpec,
+}
 
 impl LoadTagsFileCommand {
     pub fn new() -> LoadTagsFileCommand {
-        LoadTagsFileCommand {}
+        LoadTagsFileCommand {
+            spec: CommandSpec::new("load-tags-file", "Load a universal-ctags tags file")
+                .alias("load")
+                .modifying()
+                .option("p", "priority", "PRIORITY", "prefer the tags of this file over those of lower priority files")
+                .argument("tags_file"),
+        }
     }
 }
 
 impl ServerCommand for LoadTagsFileCommand {
-    fn can_execute(&self, command: &str) -> bool {
-        command.starts_with("load-tags-file ")
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
     }
 
-    fn execute(&self, command: &str, tag_database: &mut TagDatabase) -> String {
-        let tokens: Vec<&str> = command.lines().nth(0).unwrap().split(" ").collect();
-        let file_path = tokens[1].trim();
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let priority = args.parsed_value::<i32>("priority")?.unwrap_or(0);
 
-        let tag_file = TagFile::from_file(file_path);
-        tag_database.tag_files.push(tag_file);
+        let mut tag_file = TagFile::from_file(args.arg(0)).map_err(|e| CommandError::io(args.arg(0), &e))?;
+        tag_file.priority = priority;
+        let tag_count = tag_file.tags.len();
+        tag_database.add_tag_file(tag_file);
 
-        return String::from("Done");
+        Ok(Reply::record("loaded").with("file", args.arg(0)).with("tags", tag_count))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tags::TagDefinition;
-    use std::collections::HashMap;
+
+    #[test]
+    fn should_reject_malformed_priority() {
+        let command = LoadTagsFileCommand::new();
+        let mut tag_database = TagDatabase::new();
+        assert_eq!(Err(CommandError::Usage("usage: load-tags-file [-p <priority>] <tags_file>".to_string())), command.execute("load-tags-file -p high tags", &mut tag_database));
+        assert!(tag_database.tag_files.is_empty());
+    }
+
+    #[test]
+    fn should_fail_on_missing_tags_file() {
+        let command = LoadTagsFileCommand::new();
+        let mut tag_database = TagDatabase::new();
+        let error = command.execute("load-tags-file no/such/tags", &mut tag_database).unwrap_err();
+        assert_eq!("not-found", error.kind());
+        assert!(error.message().starts_with("no/such/tags: "));
+    }
 
     #[test]
     fn should_load_tags() {
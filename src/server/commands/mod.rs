@@ -1,12 +1,45 @@
 mod args;
+mod spec;
 mod echo;
 mod find_other_file;
 mod load_tags_file;
 mod describe_tag;
 mod find_tags;
+mod overrides;
+mod goto_counterpart;
+mod outline;
+mod scope_at;
+mod resolve;
+mod references;
+mod includes;
+mod header_for;
+mod grep_tags;
+mod visited;
+
+pub type CommandSpec = spec::CommandSpec;
+pub type CommandArgs = spec::CommandArgs;
 
 pub type Echo = echo::EchoCommand;
 pub type FindOtherFile = find_other_file::FindOtherFileCommand;
 pub type LoadTagsFile = load_tags_file::LoadTagsFileCommand;
 pub type DescribeTag = describe_tag::DescribeTagCommand;
 pub type FindTags = find_tags::FindTagsCommand;
+pub type Overrides = overrides::OverridesCommand;
+pub type Overridden = overrides::OverriddenCommand;
+pub type GotoDefinition = goto_counterpart::GotoDefinitionCommand;
+pub type GotoDeclaration = goto_counterpart::GotoDeclarationCommand;
+pub type Outline = outline::OutlineCommand;
+pub type ScopeAt = scope_at::ScopeAtCommand;
+pub type Resolve = resolve::ResolveCommand;
+pub type References = references::ReferencesCommand;
+pub type Includes = includes::IncludesCommand;
+pub type IncludedBy = includes::IncludedByCommand;
+pub type IncludePath = includes::IncludePathCommand;
+pub type HeaderFor = header_for::HeaderForCommand;
+pub type GrepTags = grep_tags::GrepTagsCommand;
+pub type Visited = visited::VisitedCommand;
+
+pub use self::args::quote_arg;
+pub use self::find_other_file::other_file;
+pub use self::find_tags::rank_tags;
+pub use self::resolve::resolve;
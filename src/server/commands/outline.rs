@@ -0,0 +1,86 @@
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use tags::TagDatabase;
+use tags::TagDefinition;
+use tags::scope_parents;
+use tags::tag_kind_name;
+
+pub struct OutlineCommand {
+    spec: CommandSpec,
+}
+
+impl OutlineCommand {
+    pub fn new() -> OutlineCommand {
+        OutlineCommand {
+            spec: CommandSpec::new("outline", "List the tags of a file nested by scope").argument("file"),
+        }
+    }
+}
+
+impl ServerCommand for OutlineCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let tags = tag_database.tags_in_file(args.arg(0)).map_err(CommandError::Usage)?;
+        let parents = scope_parents(&tags);
+        Ok(outline_reply(&tags, &parents, None))
+    }
+}
+
+fn outline_reply(tags: &[&TagDefinition], parents: &[Option<usize>], parent: Option<usize>) -> Reply {
+    Reply::list((0..tags.len())
+        .filter(|&i| parents[i] == parent)
+        .map(|i| {
+            let tag = tags[i];
+            Reply::record("entry")
+                .with("kind", Reply::symbol(tag_kind_name(&tag.kind())))
+                .with("name", tag.name())
+                .with("signature", tag.signature())
+                .with("line", tag.source_line())
+                .with("children", outline_reply(tags, parents, Some(i)))
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Test\ttest/Test.h\t/^class Test {$/;\"\tc\tline:14\tnamespace:Game",
+            "Test.h\ttest/Test.h\t1;\"\tF\tline:1",
+            "Game\ttest/Test.h\t/^namespace Game {$/;\"\tn\tline:3",
+            "DoTest\ttest/Test.h\t/^\tint DoTest() const {}$/;\"\tf\tline:16\tclass:Game::Test\tsignature:() const",
+            "Other\tOther.h\t/^class Other {$/;\"\tc\tline:1",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_nest_tags_by_scope() {
+        let mut tag_database = test_database();
+        let command = OutlineCommand::new();
+        assert_eq!("((entry :kind namespace :name \"Game\" :signature nil :line 3 :children \
+                    ((entry :kind class :name \"Test\" :signature nil :line 14 :children \
+                    ((entry :kind function :name \"DoTest\" :signature \"() const\" :line 16 :children nil))))))",
+                   command.execute("outline /src/test/Test.h", &mut tag_database).unwrap().to_elisp());
+    }
+
+    #[test]
+    fn should_reply_nil_for_unknown_file() {
+        let mut tag_database = test_database();
+        let command = OutlineCommand::new();
+        assert_eq!("nil", command.execute("outline Missing.h", &mut tag_database).unwrap().to_elisp());
+    }
+}
@@ -0,0 +1,107 @@
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use tags::TagDatabase;
+
+pub struct OverridesCommand {
+    spec: CommandSpec,
+}
+
+impl OverridesCommand {
+    pub fn new() -> OverridesCommand {
+        OverridesCommand {
+            spec: CommandSpec::new("overrides", "Find the overrides of a method in subclasses").argument("Class::Method"),
+        }
+    }
+}
+
+impl ServerCommand for OverridesCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        match split_method_name(args.arg(0)) {
+            None => Err(args.usage_error()),
+            Some((class_name, method_name)) => {
+                Ok(Reply::tags(&tag_database.find_overrides(class_name, method_name)))
+            }
+        }
+    }
+}
+
+pub struct OverriddenCommand {
+    spec: CommandSpec,
+}
+
+impl OverriddenCommand {
+    pub fn new() -> OverriddenCommand {
+        OverriddenCommand {
+            spec: CommandSpec::new("overridden", "Find the methods of base classes a method overrides").argument("Class::Method"),
+        }
+    }
+}
+
+impl ServerCommand for OverriddenCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        match split_method_name(args.arg(0)) {
+            None => Err(args.usage_error()),
+            Some((class_name, method_name)) => {
+                Ok(Reply::tags(&tag_database.find_overridden(class_name, method_name)))
+            }
+        }
+    }
+}
+
+/// Splits a `Class::Method` name at its last scope separator.
+fn split_method_name(qualified_name: &str) -> Option<(&str, &str)> {
+    qualified_name.rfind("::").map(|v| (&qualified_name[..v], &qualified_name[v + 2..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagDefinition;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Test\tTest.h\t/^class Test {$/;\"\tc\tline:14",
+            "PureVirtual\tTest.h\t/^\tvirtual void PureVirtual() = 0;$/;\"\tp\tline:17\tclass:Test",
+            "SubTest\tTest.h\t/^class SubTest : public Test {$/;\"\tc\tline:20\tinherits:Test",
+            "PureVirtual\tTest.h\t/^    virtual void PureVirtual() {$/;\"\tf\tline:22\tclass:SubTest",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_find_overrides() {
+        let mut tag_database = test_database();
+        let expected = Reply::tags(&tag_database.find_overrides("Test", "PureVirtual"));
+        assert_eq!(expected, OverridesCommand::new().execute("overrides Test::PureVirtual", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn should_find_overridden() {
+        let mut tag_database = test_database();
+        let expected = Reply::tags(&tag_database.find_overridden("SubTest", "PureVirtual"));
+        assert_eq!(expected, OverriddenCommand::new().execute("overridden SubTest::PureVirtual", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn should_reply_nil_without_overrides() {
+        let mut tag_database = test_database();
+        assert_eq!("nil", OverridesCommand::new().execute("overrides SubTest::PureVirtual", &mut tag_database).unwrap().to_elisp());
+    }
+}
@@ -0,0 +1,75 @@
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use tags::TagDatabase;
+
+pub struct ReferencesCommand {
+    spec: CommandSpec,
+}
+
+impl ReferencesCommand {
+    pub fn new() -> ReferencesCommand {
+        ReferencesCommand {
+            spec: CommandSpec::new("references", "List the references to a name, grouped by file").argument("tag_name"),
+        }
+    }
+}
+
+impl ServerCommand for ReferencesCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let references = tag_database.references_to(args.arg(0));
+        Ok(Reply::list(references.iter().map(|&(file, ref tags)| {
+            let references = tags.iter().map(|v| {
+                Reply::record("reference")
+                    .with("line", v.source_line())
+                    .with("roles", Reply::list(v.roles().into_iter().map(Reply::from)))
+                    .with("tag", Reply::tag(v))
+            });
+            Reply::record("file").with("path", file).with("references", Reply::list(references))
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagDefinition;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Test.h\tMain.cpp\t/^#include \"Test.h\"$/;\"\th\tline:1\troles:local",
+            "Test.h\tTest.h\t1;\"\tF\tline:1",
+            "Test.h\tOther.cpp\t/^#include \"Test.h\"$/;\"\th\tline:2\troles:local",
+            "Test.h\tMain.cpp\t/^#include \"Test.h\"$/;\"\th\tline:7\troles:local",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_group_references_by_file() {
+        let mut tag_database = test_database();
+        let reply = ReferencesCommand::new().execute("references Test.h", &mut tag_database).unwrap().to_elisp();
+        assert!(reply.starts_with("((file :path \"Main.cpp\" :references ((reference :line 1 :roles (\"local\")"));
+        assert!(reply.contains("(reference :line 7 :roles (\"local\")"));
+        assert!(reply.contains("(file :path \"Other.cpp\" :references ((reference :line 2"));
+        assert!(!reply.contains(":kind file"));
+    }
+
+    #[test]
+    fn should_reply_nil_without_references() {
+        let mut tag_database = test_database();
+        assert_eq!("nil", ReferencesCommand::new().execute("references Missing.h", &mut tag_database).unwrap().to_elisp());
+    }
+}
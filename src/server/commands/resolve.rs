@@ -0,0 +1,231 @@
+use std::path::Path;
+
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use server::commands::find_other_file::is_other_file;
+use tags::TagDatabase;
+use tags::TagDefinition;
+use tags::TagKind;
+use tags::tag_definition::same_file;
+
+pub struct ResolveCommand {
+    spec: CommandSpec,
+}
+
+impl ResolveCommand {
+    pub fn new() -> ResolveCommand {
+        ResolveCommand {
+            spec: CommandSpec::new("resolve", "Find the definition of the identifier at a column of a line")
+                .argument("file")
+                .argument("line")
+                .argument("column")
+                .argument("line_text"),
+        }
+    }
+}
+
+impl ServerCommand for ResolveCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let line = args.parsed_arg::<usize>(1)?;
+        let column = args.parsed_arg::<usize>(2)?;
+
+        Ok(match resolve(tag_database, args.arg(0), line, column, args.arg(3)) {
+            None => Reply::Nil,
+            Some(tag) => Reply::tag(tag),
+        })
+    }
+}
+
+/// Finds the best definition for the identifier under `column` (zero based, in characters).
+pub fn resolve<'a>(tag_database: &'a TagDatabase,
+                   file_path: &str,
+                   line: usize,
+                   column: usize,
+                   line_text: &str) -> Option<&'a TagDefinition> {
+    let identifier = identifier_at(line_text, column)?;
+    let name = match identifier.rfind("::") {
+        None => &identifier[..],
+        Some(v) => &identifier[v + 2..],
+    };
+
+    let candidates: Vec<&TagDefinition> = tag_database.all_definitions()
+        .filter(|v| v.kind() != TagKind::File && v.name() == name)
+        .collect();
+
+    // prefer the candidates the qualification agrees with, if any
+    let qualified_suffix = format!("::{}", identifier);
+    let qualified_candidates: Vec<&TagDefinition> = candidates.iter().cloned()
+        .filter(|v| {
+            let qualified_name = v.qualified_name();
+            qualified_name == identifier || qualified_name.ends_with(&qualified_suffix)
+        })
+        .collect();
+    let candidates = if qualified_candidates.is_empty() { candidates } else { qualified_candidates };
+
+    // an ambiguous file leaves only the other hints
+    let enclosing_class = tag_database.scope_at(file_path, line).ok().and_then(|v| v).and_then(|(tag, _, _)| {
+        if tag.is_class() { Some(tag.qualified_name()) } else { tag.scope_name().map(|v| v.to_string()) }
+    });
+    let module = module_root(file_path);
+
+    let mut best: Option<(usize, &TagDefinition)> = None;
+    for candidate in candidates {
+        let score = if candidate.is_in_file(file_path) {
+            5
+        } else if enclosing_class.is_some() && candidate.scope_name() == enclosing_class.as_ref().map(|v| &v[..]) {
+            4
+        } else if is_other_file(file_path, candidate.source_file()) {
+            3
+        } else if same_file(module_root(candidate.source_file()), module) {
+            2
+        } else {
+            1
+        };
+        if best.is_none() || best.unwrap().0 < score {
+            best = Some((score, candidate));
+        }
+    }
+
+    best.map(|v| v.1)
+}
+
+/// The possibly qualified identifier (`A::B`) under `column`. Qualifiers to the right of the
+/// cursor are not included, so on `A` in `A::B` the identifier is `A`.
+pub fn identifier_at(line_text: &str, column: usize) -> Option<String> {
+    let chars: Vec<char> = line_text.chars().collect();
+    let is_identifier_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    let mut end = column.min(chars.len());
+    if end == chars.len() || !is_identifier_char(chars[end]) {
+        // the cursor sits right after the identifier
+        if end == 0 || !is_identifier_char(chars[end - 1]) {
+            return None;
+        }
+    } else {
+        while end < chars.len() && is_identifier_char(chars[end]) {
+            end += 1;
+        }
+    }
+
+    let mut start = end;
+    loop {
+        while start > 0 && is_identifier_char(chars[start - 1]) {
+            start -= 1;
+        }
+        let has_qualifier = start >= 3 && chars[start - 1] == ':' && chars[start - 2] == ':'
+            && is_identifier_char(chars[start - 3]);
+        if !has_qualifier {
+            break;
+        }
+        start -= 2;
+    }
+
+    if start == end {
+        return None;
+    }
+    Some(chars[start..end].iter().collect())
+}
+
+/// The module a file belongs to: the directory holding its `Public`, `Private` or `Classes`
+/// folder, or the file's own directory outside such a layout.
+fn module_root(file_path: &str) -> &str {
+    let path = Path::new(file_path);
+    for ancestor in path.ancestors().skip(1) {
+        let is_module_folder = matches!(ancestor.file_name().and_then(|v| v.to_str()),
+                                        Some("Public") | Some("Private") | Some("Classes"));
+        if is_module_folder {
+            return ancestor.parent().and_then(|v| v.to_str()).unwrap_or("");
+        }
+    }
+    path.parent().and_then(|v| v.to_str()).unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use server::commands::quote_arg;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Count\tEngine/Core/Public/Other.h\t/^\tint Count;$/;\"\tm\tline:3\tclass:Other",
+            "Count\tGame/Actor/Public/Pawn.h\t/^\tint Count;$/;\"\tm\tline:5\tclass:Pawn",
+            "Count\tGame/Actor/Public/Actor.h\t/^\tint Count;$/;\"\tm\tline:8\tclass:Actor",
+            "Actor\tGame/Actor/Public/Actor.h\t/^class Actor {$/;\"\tc\tline:4\tend:12",
+            "Tick\tGame/Actor/Private/Actor.cpp\t/^void Actor::Tick() {$/;\"\tf\tline:10\tclass:Actor\tend:14",
+            "Spawn\tGame/Actor/Private/Actor.cpp\t/^void Spawn() {$/;\"\tf\tline:20\tend:24",
+            "Reset\tEngine/Core/Public/Other.h\t/^\tvoid Reset();$/;\"\tp\tline:4\tclass:Other",
+            "Reset\tGame/Actor/Public/Pawn.h\t/^\tvoid Reset();$/;\"\tp\tline:6\tclass:Pawn",
+            "Reset\tGame/Actor/Public/Actor.h\t/^void Reset();$/;\"\tp\tline:14",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_extract_identifier_at_column() {
+        assert_eq!(Some("Count".to_string()), identifier_at("    return Count + 1;", 13));
+        assert_eq!(Some("Count".to_string()), identifier_at("    return Count + 1;", 16));
+        assert_eq!(Some("Game::Actor".to_string()), identifier_at("Game::Actor* Owner;", 8));
+        assert_eq!(Some("Game".to_string()), identifier_at("Game::Actor* Owner;", 1));
+        assert_eq!(None, identifier_at("    return Count + 1;", 2));
+    }
+
+    #[test]
+    fn should_prefer_enclosing_class_scope() {
+        let tag_database = test_database();
+        let tag = resolve(&tag_database, "Game/Actor/Private/Actor.cpp", 11, 4, "\tCount++;").unwrap();
+        assert_eq!("Game/Actor/Public/Actor.h", tag.source_file());
+    }
+
+    #[test]
+    fn should_prefer_counterpart_header() {
+        let tag_database = test_database();
+        let tag = resolve(&tag_database, "Game/Actor/Private/Actor.cpp", 21, 2, "\tReset();").unwrap();
+        assert_eq!("Game/Actor/Public/Actor.h", tag.source_file());
+    }
+
+    #[test]
+    fn should_prefer_qualified_match() {
+        let tag_database = test_database();
+        let tag = resolve(&tag_database, "Game/Actor/Private/Actor.cpp", 21, 8, "\tOther::Reset();").unwrap();
+        assert_eq!("Engine/Core/Public/Other.h", tag.source_file());
+    }
+
+    #[test]
+    fn should_prefer_same_module() {
+        let tag_database = test_database();
+        let tag = resolve(&tag_database, "Game/Actor/Private/Spawner.cpp", 3, 2, "\tCount = 0;").unwrap();
+        assert_eq!("Game/Actor/Public/Pawn.h", tag.source_file());
+    }
+
+    #[test]
+    fn should_resolve_in_lines_with_non_ascii_text() {
+        let mut tag_database = test_database();
+        let reply = ResolveCommand::new().execute("resolve Game/Actor/Private/Actor.cpp 11 7 \"\tÄÖ = Count;\"", &mut tag_database).unwrap();
+        let tag = resolve(&tag_database, "Game/Actor/Private/Actor.cpp", 11, 7, "\tÄÖ = Count;").unwrap();
+        assert_eq!("Game/Actor/Public/Actor.h", tag.source_file());
+        assert_eq!(Reply::tag(tag), reply);
+    }
+
+    #[test]
+    fn should_resolve_in_lines_with_quotes() {
+        let mut tag_database = test_database();
+        let request = format!("resolve Game/Actor/Private/Actor.cpp 21 22 {}", quote_arg("\tLog(\"Reset \\\"%s\\\"\", Reset());"));
+        let reply = ResolveCommand::new().execute(&request, &mut tag_database).unwrap();
+        let tag = resolve(&tag_database, "Game/Actor/Private/Actor.cpp", 21, 22, "\tLog(\"Reset \\\"%s\\\"\", Reset());").unwrap();
+        assert_eq!("Game/Actor/Public/Actor.h", tag.source_file());
+        assert_eq!(Reply::tag(tag), reply);
+    }
+}
@@ -0,0 +1,75 @@
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use tags::TagDatabase;
+
+pub struct ScopeAtCommand {
+    spec: CommandSpec,
+}
+
+impl ScopeAtCommand {
+    pub fn new() -> ScopeAtCommand {
+        ScopeAtCommand {
+            spec: CommandSpec::new("scope-at", "Find the innermost function, class or namespace around a line")
+                .argument("file")
+                .argument("line"),
+        }
+    }
+}
+
+impl ServerCommand for ScopeAtCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let line = args.parsed_arg::<usize>(1)?;
+
+        Ok(match tag_database.scope_at(args.arg(0), line).map_err(CommandError::Usage)? {
+            None => Reply::Nil,
+            Some((tag, start, end)) => {
+                Reply::record("scope")
+                    .with("name", tag.qualified_name())
+                    .with("start", start)
+                    .with("end", if end == usize::MAX { None } else { Some(end) })
+                    .with("tag", Reply::tag(tag))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagDefinition;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Test\tTest.h\t/^class Test {$/;\"\tc\tline:14\tend:17",
+            "DoTest\tTest.h\t/^\tint DoTest() const {}$/;\"\tf\tline:16\tclass:Test\tend:16",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_reply_with_innermost_scope() {
+        let mut tag_database = test_database();
+        let expected = format!("(scope :name \"Test::DoTest\" :start 16 :end 16 :tag {})",
+                               Reply::tag(tag_database.scope_at("Test.h", 16).unwrap().unwrap().0).to_elisp());
+        assert_eq!(expected, ScopeAtCommand::new().execute("scope-at Test.h 16", &mut tag_database).unwrap().to_elisp());
+    }
+
+    #[test]
+    fn should_reply_nil_outside_scopes() {
+        let mut tag_database = test_database();
+        assert_eq!(Reply::Nil, ScopeAtCommand::new().execute("scope-at Test.h 20", &mut tag_database).unwrap());
+    }
+}
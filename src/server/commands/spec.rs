@@ -0,0 +1,288 @@
+use std::str::FromStr;
+
+use getopts::Matches;
+use getopts::Options;
+
+use server::commands::args::split_args;
+use server::error::CommandError;
+use server::reply::Reply;
+
+/// The name a command is registered under, its aliases, a one-line summary and the options and
+/// arguments it takes. Requests are parsed and help is generated from it.
+pub struct CommandSpec {
+    name: &'static str,
+    aliases: Vec<&'static str>,
+    summary: &'static str,
+    options: Options,
+    option_specs: Vec<OptionSpec>,
+    arguments: Vec<ArgumentSpec>,
+    /// Whether the command changes the tag database, which a panic may leave half changed.
+    modifying: bool,
+}
+
+struct OptionSpec {
+    short: &'static str,
+    long: &'static str,
+    /// The name of the option's value, None for flags.
+    value: Option<&'static str>,
+    required: bool,
+    description: &'static str,
+}
+
+#[derive(PartialEq)]
+enum Arity {
+    Required,
+    Optional,
+    Variadic,
+}
+
+struct ArgumentSpec {
+    name: &'static str,
+    arity: Arity,
+}
+
+/// The options and arguments of a request, checked against the spec of its command.
+pub struct CommandArgs {
+    matches: Matches,
+    usage: String,
+}
+
+impl CommandSpec {
+    pub fn new(name: &'static str, summary: &'static str) -> CommandSpec {
+        CommandSpec {
+            name,
+            aliases: Vec::new(),
+            summary,
+            options: Options::new(),
+            option_specs: Vec::new(),
+            arguments: Vec::new(),
+            modifying: false,
+        }
+    }
+
+    pub fn alias(mut self, alias: &'static str) -> CommandSpec {
+        self.aliases.push(alias);
+        self
+    }
+
+    pub fn flag(mut self, short: &'static str, long: &'static str, description: &'static str) -> CommandSpec {
+        self.options.optflag(short, long, description);
+        self.option_specs.push(OptionSpec { short, long, value: None, required: false, description });
+        self
+    }
+
+    pub fn option(mut self, short: &'static str, long: &'static str, value: &'static str, description: &'static str) -> CommandSpec {
+        self.options.optopt(short, long, description, value);
+        self.option_specs.push(OptionSpec { short, long, value: Some(value), required: false, description });
+        self
+    }
+
+    pub fn required_option(mut self, short: &'static str, long: &'static str, value: &'static str, description: &'static str) -> CommandSpec {
+        self.options.reqopt(short, long, description, value);
+        self.option_specs.push(OptionSpec { short, long, value: Some(value), required: true, description });
+        self
+    }
+
+    pub fn argument(mut self, name: &'static str) -> CommandSpec {
+        self.arguments.push(ArgumentSpec { name, arity: Arity::Required });
+        self
+    }
+
+    pub fn optional_argument(mut self, name: &'static str) -> CommandSpec {
+        self.arguments.push(ArgumentSpec { name, arity: Arity::Optional });
+        self
+    }
+
+    /// Any number of arguments, after all the others.
+    pub fn variadic_argument(mut self, name: &'static str) -> CommandSpec {
+        self.arguments.push(ArgumentSpec { name, arity: Arity::Variadic });
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn aliases(&self) -> &[&'static str] {
+        &self.aliases
+    }
+
+    /// Marks the command as one that changes the tag database.
+    pub fn modifying(mut self) -> CommandSpec {
+        self.modifying = true;
+        self
+    }
+
+    pub fn is_modifying(&self) -> bool {
+        self.modifying
+    }
+
+    /// The usage line, e.g. `grep-tags [-e] [-n <count>] <pattern>`.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for option in &self.option_specs {
+            let name = if option.short.is_empty() { format!("--{}", option.long) } else { format!("-{}", option.short) };
+            let option_usage = match option.value {
+                None => name,
+                Some(value) => format!("{} <{}>", name, value.to_lowercase()),
+            };
+            if option.required {
+                usage.push_str(&format!(" {}", option_usage));
+            } else {
+                usage.push_str(&format!(" [{}]", option_usage));
+            }
+        }
+        for argument in &self.arguments {
+            match argument.arity {
+                Arity::Required => usage.push_str(&format!(" <{}>", argument.name)),
+                Arity::Optional => usage.push_str(&format!(" [<{}>]", argument.name)),
+                Arity::Variadic => usage.push_str(&format!(" [<{}>...]", argument.name)),
+            }
+        }
+        usage
+    }
+
+    pub fn usage_error(&self) -> CommandError {
+        CommandError::Usage(format!("usage: {}", self.usage()))
+    }
+
+    /// Whether `name`, the short or long name of an option, takes a value; None when the
+    /// command has no such option.
+    pub fn takes_value(&self, name: &str) -> Option<bool> {
+        self.option_specs.iter()
+            .find(|v| v.long == name || (!v.short.is_empty() && v.short == name))
+            .map(|v| v.value.is_some())
+    }
+
+    /// The options and arguments of `request`, which starts with the command name or an alias.
+    pub fn parse(&self, request: &str) -> Result<CommandArgs, CommandError> {
+        let args = split_args(request).map_err(CommandError::Parse)?;
+        let args: Vec<&str> = args.iter().map(|v| &v[..]).collect();
+        self.parse_args(&args[1..])
+    }
+
+    /// The options and arguments of a request already split into `args`, without the command
+    /// name.
+    pub fn parse_args(&self, args: &[&str]) -> Result<CommandArgs, CommandError> {
+        let matches = self.options.parse(args)
+            .map_err(|e| CommandError::Usage(format!("{}; usage: {}", e, self.usage())))?;
+
+        let required = self.arguments.iter().filter(|v| v.arity == Arity::Required).count();
+        let variadic = self.arguments.iter().any(|v| v.arity == Arity::Variadic);
+        let count = matches.free.len();
+        if count < required || (!variadic && count > self.arguments.len()) {
+            return Err(self.usage_error());
+        }
+
+        Ok(CommandArgs {
+            matches,
+            usage: format!("usage: {}", self.usage()),
+        })
+    }
+
+    /// The name and summary of the command, as listed by `help`.
+    pub fn summary_reply(&self) -> Reply {
+        Reply::record("command").with("name", self.name).with("summary", self.summary)
+    }
+
+    /// Everything about the command, as described by `help <command>`.
+    pub fn help_reply(&self) -> Reply {
+        let options = self.option_specs.iter().map(|v| {
+            Reply::record("option")
+                .with("short", if v.short.is_empty() { None } else { Some(format!("-{}", v.short)) })
+                .with("long", format!("--{}", v.long))
+                .with("value", v.value)
+                .with("required", v.required)
+                .with("description", v.description)
+        });
+        Reply::record("command")
+            .with("name", self.name)
+            .with("aliases", Reply::list(self.aliases.iter().map(|&v| Reply::from(v))))
+            .with("summary", self.summary)
+            .with("usage", self.usage())
+            .with("options", Reply::list(options))
+    }
+}
+
+impl CommandArgs {
+    /// The positional argument at `index`, which the spec guarantees for required arguments.
+    pub fn arg(&self, index: usize) -> &str {
+        &self.matches.free[index]
+    }
+
+    pub fn optional_arg(&self, index: usize) -> Option<&str> {
+        self.matches.free.get(index).map(|v| &v[..])
+    }
+
+    pub fn args(&self) -> Vec<&str> {
+        self.matches.free.iter().map(|v| &v[..]).collect()
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.matches.opt_present(name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<String> {
+        self.matches.opt_str(name)
+    }
+
+    /// The value of an option parsed as `T`, or the usage of the command if it does not parse.
+    pub fn parsed_value<T: FromStr>(&self, name: &str) -> Result<Option<T>, CommandError> {
+        match self.matches.opt_str(name) {
+            None => Ok(None),
+            Some(v) => v.parse::<T>().map(Some).map_err(|_| self.usage_error()),
+        }
+    }
+
+    /// The positional argument at `index` parsed as `T`, or the usage of the command if it does
+    /// not parse.
+    pub fn parsed_arg<T: FromStr>(&self, index: usize) -> Result<T, CommandError> {
+        self.arg(index).parse::<T>().map_err(|_| self.usage_error())
+    }
+
+    pub fn usage_error(&self) -> CommandError {
+        CommandError::Usage(self.usage.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grep_spec() -> CommandSpec {
+        CommandSpec::new("grep-tags", "Find tags by substring")
+            .alias("grep")
+            .flag("e", "regex", "match the pattern as a regular expression")
+            .option("n", "limit", "COUNT", "reply with up to COUNT tags")
+            .option("", "in", "FIELD", "match against FIELD")
+            .argument("pattern")
+            .optional_argument("file")
+    }
+
+    #[test]
+    fn should_generate_usage() {
+        assert_eq!("grep-tags [-e] [-n <count>] [--in <field>] <pattern> [<file>]", grep_spec().usage());
+        let spec = CommandSpec::new("find", "Find tags").required_option("k", "kind", "KIND", "tag kind").variadic_argument("query");
+        assert_eq!("find -k <kind> [<query>...]", spec.usage());
+    }
+
+    #[test]
+    fn should_parse_options_and_arguments() {
+        let args = grep_spec().parse("grep -e -n 5 \"Spawn Actor\"").unwrap();
+        assert!(args.flag("regex"));
+        assert_eq!(Ok(Some(5)), args.parsed_value::<usize>("limit"));
+        assert_eq!(vec!["Spawn Actor"], args.args());
+        assert_eq!(None, args.optional_arg(1));
+    }
+
+    #[test]
+    fn should_reject_malformed_requests() {
+        let usage = "usage: grep-tags [-e] [-n <count>] [--in <field>] <pattern> [<file>]";
+        let usage_error = |v: &str| CommandError::Usage(v.to_string());
+        assert_eq!(Some(usage_error(usage)), grep_spec().parse("grep-tags").err());
+        assert_eq!(Some(usage_error(usage)), grep_spec().parse("grep-tags a b c").err());
+        assert_eq!(Some(usage_error(&format!("Unrecognized option: 'x'; {}", usage))), grep_spec().parse("grep-tags -x a").err());
+        assert_eq!(Err(usage_error(usage)), grep_spec().parse("grep-tags -n many a").unwrap().parsed_value::<usize>("limit"));
+        assert_eq!(Some(CommandError::Parse("Unbalanced quote in request grep-tags \"Spawn".to_string())), grep_spec().parse("grep-tags \"Spawn").err());
+    }
+}
@@ -0,0 +1,94 @@
+use server::ServerCommand;
+use server::commands::CommandArgs;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+use server::reply::Reply;
+use tags::TagDatabase;
+use tags::visit_history::DEFAULT_CLIENT;
+
+pub struct VisitedCommand {
+    spec: CommandSpec,
+}
+
+impl VisitedCommand {
+    pub fn new() -> VisitedCommand {
+        VisitedCommand {
+            spec: CommandSpec::new("visited", "Record a visit to the tag at a line, to rank it higher in find")
+                .modifying()
+                .option("c", "client", "NAME", "record the visit for the client NAME rather than the default one")
+                .argument("file")
+                .argument("line"),
+        }
+    }
+}
+
+impl ServerCommand for VisitedCommand {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let line = args.parsed_arg::<usize>(1)?;
+        let client = client_name(args)?;
+
+        if !tag_database.record_visit(&client, args.arg(0), line).map_err(CommandError::Usage)? {
+            return Ok(Reply::Nil);
+        }
+        Ok(match tag_database.tag_at(args.arg(0), line).map_err(CommandError::Usage)? {
+            None => Reply::Nil,
+            Some(tag) => Reply::tag(tag),
+        })
+    }
+}
+
+/// The client named by the `client` option of a request, whose visits are its own.
+pub fn client_name(args: &CommandArgs) -> Result<String, CommandError> {
+    match args.value("client") {
+        None => Ok(DEFAULT_CLIENT.to_string()),
+        Some(ref v) if v.is_empty() || v.contains(char::is_control) => Err(args.usage_error()),
+        Some(v) => Ok(v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagDefinition;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Test\tTest.h\t/^class Test {$/;\"\tc\tline:14\tend:20",
+            "DoTest\tTest.h\t/^\tint DoTest() const {}$/;\"\tf\tline:16\tclass:Test\tend:18",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_record_tag_on_line_or_around_it() {
+        let mut tag_database = test_database();
+        let command = VisitedCommand::new();
+
+        let do_test = Reply::tag(&tag_database.tag_files[0].tags[1]);
+        assert_eq!(do_test, command.execute("visited Test.h 16", &mut tag_database).unwrap());
+        assert_eq!(do_test, command.execute("visited Test.h 17", &mut tag_database).unwrap());
+        assert_eq!(20, tag_database.frecency(DEFAULT_CLIENT, &tag_database.tag_files[0].tags[1]));
+        assert_eq!(Reply::Nil, command.execute("visited Test.h 40", &mut tag_database).unwrap());
+    }
+
+    #[test]
+    fn should_record_visits_per_client() {
+        let mut tag_database = test_database();
+        let command = VisitedCommand::new();
+
+        command.execute("visited --client emacs Test.h 16", &mut tag_database).unwrap();
+        assert_eq!(10, tag_database.frecency("emacs", &tag_database.tag_files[0].tags[1]));
+        assert_eq!(0, tag_database.frecency(DEFAULT_CLIENT, &tag_database.tag_files[0].tags[1]));
+        assert!(command.execute("visited --client \"\" Test.h 16", &mut tag_database).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::io;
+
+use server::reply::Reply;
+
+/// Why a request failed. Every kind is replied as an `error` record of its own `kind`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CommandError {
+    /// The request does not match the usage of the command.
+    Usage(String),
+    /// The command, file or other named thing does not exist.
+    NotFound(String),
+    /// Reading a file failed.
+    Io(String),
+    /// An argument, such as a query or a regex, or the request itself does not parse.
+    Parse(String),
+    /// The command failed unexpectedly.
+    Internal(String),
+}
+
+impl CommandError {
+    /// The error for an io error on `path`. Missing files are not found errors.
+    pub fn io(path: &str, error: &io::Error) -> CommandError {
+        let message = format!("{}: {}", path, error);
+        match error.kind() {
+            io::ErrorKind::NotFound => CommandError::NotFound(message),
+            _ => CommandError::Io(message),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            CommandError::Usage(_) => "usage",
+            CommandError::NotFound(_) => "not-found",
+            CommandError::Io(_) => "io",
+            CommandError::Parse(_) => "parse",
+            CommandError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match *self {
+            CommandError::Usage(ref v)
+            | CommandError::NotFound(ref v)
+            | CommandError::Io(ref v)
+            | CommandError::Parse(ref v)
+            | CommandError::Internal(ref v) => v,
+        }
+    }
+
+    /// The reply to the client, e.g. `(error :kind usage :message "usage: outline <file>")`.
+    pub fn to_reply(&self) -> Reply {
+        Reply::record("error").with("kind", Reply::symbol(self.kind())).with("message", self.message())
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} error: {}", self.kind(), self.message())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_reply_with_kind_and_message() {
+        assert_eq!("(error :kind usage :message \"usage: outline <file>\")",
+                   CommandError::Usage("usage: outline <file>".to_string()).to_reply().to_elisp());
+        assert_eq!("{\"type\":\"error\",\"kind\":\"not-found\",\"message\":\"tags: gone\"}",
+                   CommandError::io("tags", &io::Error::new(io::ErrorKind::NotFound, "gone")).to_reply().to_json());
+    }
+}
@@ -0,0 +1,312 @@
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+
+use serde_json;
+use serde_json::Value;
+
+use server::Server;
+use server::commands::CommandSpec;
+use server::error::CommandError;
+
+/// JSON-RPC 2.0 error codes.
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// Commands which fail for any reason but their usage. The kind of failure is the `kind` of the
+/// error's data.
+pub const COMMAND_ERROR: i64 = -32000;
+
+/// Whether the first bytes of a connection start a Content-Length framed message rather than
+/// a text command.
+pub fn is_framed(first_bytes: &[u8]) -> bool {
+    let header = b"content-length:";
+    first_bytes.len() >= header.len() && first_bytes[..header.len()].eq_ignore_ascii_case(header)
+}
+
+/// The largest message body read. A larger Content-Length is an error rather than a buffer of
+/// that size.
+pub const MAX_CONTENT_LENGTH: usize = 8 * 1024 * 1024;
+
+/// The longest header line read, including its line break.
+const MAX_HEADER_LENGTH: usize = 8 * 1024;
+
+/// Reads the body of the next Content-Length framed message, or None at the end of the stream.
+/// A missing, malformed or too large Content-Length fails with `InvalidData`, after which the
+/// stream is no longer at the start of a message.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut header = String::new();
+        if Read::take(&mut *reader, MAX_HEADER_LENGTH as u64).read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        if header.len() == MAX_HEADER_LENGTH && !header.ends_with('\n') {
+            return Err(invalid_data(format!("Header line exceeds the limit of {} bytes", MAX_HEADER_LENGTH)));
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(separator) = header.find(':') {
+            if header[..separator].eq_ignore_ascii_case("content-length") {
+                let value = header[separator + 1..].trim();
+                let length = value.parse::<usize>()
+                    .map_err(|_| invalid_data(format!("Invalid Content-Length '{}'", value)))?;
+                if length > MAX_CONTENT_LENGTH {
+                    return Err(invalid_data(format!("Content-Length {} exceeds the limit of {} bytes", length, MAX_CONTENT_LENGTH)));
+                }
+                content_length = Some(length);
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length.unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+    String::from_utf8(body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn write_message<W: Write>(writer: &mut W, body: &str) -> io::Result<()> {
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// The response to a message `read_message` failed to read, sent before closing the
+/// connection.
+pub fn invalid_message_response(error: &io::Error) -> String {
+    error_response(&Value::Null, INVALID_REQUEST, &error.to_string()).to_string()
+}
+
+impl<'a> Server<'a> {
+    /// The response to a JSON-RPC request or batch of requests, or None when it only held
+    /// notifications.
+    pub fn handle_json_rpc(&mut self, message: &str) -> Option<String> {
+        let value: Value = match serde_json::from_str(message) {
+            Ok(v) => v,
+            Err(e) => return Some(error_response(&Value::Null, PARSE_ERROR, &format!("Parse error: {}", e)).to_string()),
+        };
+
+        let response = match value {
+            Value::Array(requests) => {
+                if requests.is_empty() {
+                    return Some(error_response(&Value::Null, INVALID_REQUEST, "Empty batch").to_string());
+                }
+                let responses: Vec<Value> = requests.iter().filter_map(|v| self.handle_json_rpc_request(v)).collect();
+                if responses.is_empty() { None } else { Some(Value::Array(responses)) }
+            }
+            request => self.handle_json_rpc_request(&request),
+        };
+        response.map(|v| v.to_string())
+    }
+
+    fn handle_json_rpc_request(&mut self, request: &Value) -> Option<Value> {
+        let id = request.get("id");
+        let response_id = id.cloned().unwrap_or(Value::Null);
+        let method = match (request.get("jsonrpc").and_then(|v| v.as_str()), request.get("method").and_then(|v| v.as_str())) {
+            (Some("2.0"), Some(method)) => method,
+            _ => return Some(error_response(&response_id, INVALID_REQUEST, "Invalid request")),
+        };
+
+        let args = match self.command_spec(method) {
+            None => Err(error_response(&response_id, METHOD_NOT_FOUND, &format!("Method not found: {}", method))),
+            Some(spec) => match command_args(spec, request.get("params")) {
+                Err(e) => Err(error_response(&response_id, INVALID_PARAMS, &e)),
+                Ok(args) => {
+                    info!("Executing JSON-RPC method \"{}\": {:?}", method, args);
+                    let args: Vec<&str> = args.iter().map(|v| &v[..]).collect();
+                    spec.parse_args(&args).map_err(|e| command_error_response(&response_id, &e))
+                }
+            },
+        };
+        let response = match args.map(|args| self.execute_command(method, &args)) {
+            Err(response) => response,
+            Ok(Err(e)) => command_error_response(&response_id, &e),
+            Ok(Ok(reply)) => json!({"jsonrpc": "2.0", "id": response_id, "result": reply.to_json_value()}),
+        };
+
+        // notifications get no response
+        id.map(|_| response)
+    }
+}
+
+fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+fn command_error_response(id: &Value, error: &CommandError) -> Value {
+    let code = match *error {
+        CommandError::Usage(_) => INVALID_PARAMS,
+        _ => COMMAND_ERROR,
+    };
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": error.message(), "data": {"kind": error.kind()}}})
+}
+
+/// The options and arguments of a method call, checked against the spec of its command. Named
+/// params are options of the spec, `--name value` or just `--name` for true, and the `args`
+/// param, or params given by position, are arguments. Arguments follow `--`, so none of them
+/// is taken for an option.
+fn command_args(spec: &CommandSpec, params: Option<&Value>) -> Result<Vec<String>, String> {
+    let mut options: Vec<String> = Vec::new();
+    let mut args: Vec<String> = Vec::new();
+
+    match params {
+        None | Some(&Value::Null) => {}
+        Some(Value::Array(values)) => {
+            for value in values {
+                args.push(argument(value)?);
+            }
+        }
+        Some(Value::Object(values)) => {
+            for (name, value) in values {
+                if name == "args" {
+                    match *value {
+                        Value::Array(ref values) => {
+                            for value in values {
+                                args.push(argument(value)?);
+                            }
+                        }
+                        ref value => args.push(argument(value)?),
+                    }
+                    continue;
+                }
+
+                let takes_value = spec.takes_value(name)
+                    .ok_or_else(|| format!("Unrecognized param '{}'; usage: {}", name, spec.usage()))?;
+                let option = if name.chars().count() == 1 { format!("-{}", name) } else { format!("--{}", name) };
+                match (takes_value, value) {
+                    (_, &Value::Null) | (false, &Value::Bool(false)) => {}
+                    (false, &Value::Bool(true)) => options.push(option),
+                    (false, value) => return Err(format!("Param '{}' is a flag, not {}", name, value)),
+                    (true, value) => {
+                        options.push(option);
+                        options.push(argument(value)?);
+                    }
+                }
+            }
+        }
+        Some(_) => return Err("Params must be an object or an array".to_string()),
+    }
+
+    options.push("--".to_string());
+    options.extend(args);
+    Ok(options)
+}
+
+/// A param value as a command argument.
+fn argument(value: &Value) -> Result<String, String> {
+    match *value {
+        Value::String(ref v) => Ok(v.clone()),
+        Value::Number(ref v) => Ok(v.to_string()),
+        Value::Bool(v) => Ok(v.to_string()),
+        _ => Err(format!("Unsupported param value {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+    use server::commands::Echo;
+    use server::commands::FindTags;
+    use server::ServerCommand;
+    use tags::TagDefinition;
+    use tags::TagFile;
+
+    fn test_server<'a>() -> Server<'a> {
+        let mut server = Server::new();
+        server.add_command(Box::new(Echo::new()));
+        server.add_command(Box::new(FindTags::new()));
+
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("SpawnActor\tWorld.h\t/^\tAActor* SpawnActor();$/;\"\tp\tline:3".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("SpawnActors\tWorld.h\t/^\tvoid SpawnActors();$/;\"\tp\tline:4".to_string()));
+        server.tag_database.add_tag_file(tag_file);
+        server
+    }
+
+    #[test]
+    fn should_read_framed_messages() {
+        let input = "Content-Length: 7\r\nContent-Type: application/json\r\n\r\n{\"a\":1}content-length: 2\r\n\r\n[]";
+        assert!(is_framed(input.as_bytes()));
+        assert!(!is_framed(b"find Spawn"));
+
+        let mut reader = BufReader::new(input.as_bytes());
+        assert_eq!("{\"a\":1}", read_message(&mut reader).unwrap().unwrap());
+        assert_eq!("[]", read_message(&mut reader).unwrap().unwrap());
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_reject_malformed_content_lengths() {
+        let read = |input: &str| read_message(&mut BufReader::new(input.as_bytes())).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, read("Content-Length: 7a\r\n\r\n{\"a\":1}").kind());
+        let error = read(&format!("Content-Length: {}\r\n\r\n", MAX_CONTENT_LENGTH + 1));
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert_eq!(format!("{{\"jsonrpc\":\"2.0\",\"id\":null,\"error\":{{\"code\":-32600,\"message\":\"Content-Length {} exceeds the limit of {} bytes\"}}}}",
+                           MAX_CONTENT_LENGTH + 1, MAX_CONTENT_LENGTH),
+                   invalid_message_response(&error));
+    }
+
+    #[test]
+    fn should_reject_too_long_header_lines() {
+        let input = format!("X-Padding: {}\r\nContent-Length: 2\r\n\r\n[]", "a".repeat(MAX_HEADER_LENGTH));
+        let error = read_message(&mut BufReader::new(input.as_bytes())).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        assert_eq!(format!("Header line exceeds the limit of {} bytes", MAX_HEADER_LENGTH), error.to_string());
+    }
+
+    #[test]
+    fn should_map_named_params_to_command_options() {
+        let spec = FindTags::new();
+        let spec = spec.spec();
+        let params = json!({"limit": 5, "references": true, "m": false, "args": ["Spawn", "kind:function \"prototype\"", "-n"]});
+        assert_eq!(vec!["--limit", "5", "--references", "--", "Spawn", "kind:function \"prototype\"", "-n"],
+                   command_args(spec, Some(&params)).unwrap());
+        assert_eq!(vec!["--", "-n", "5"], command_args(spec, Some(&json!(["-n", 5]))).unwrap());
+        assert!(command_args(spec, Some(&json!("Spawn"))).is_err());
+        assert!(command_args(spec, Some(&json!({"references": "yes"}))).is_err());
+        assert_eq!(Err(format!("Unrecognized param 'limit 5 --references'; usage: {}", spec.usage())),
+                   command_args(spec, Some(&json!({"limit 5 --references": true}))));
+    }
+
+    #[test]
+    fn should_reply_with_result_or_error_objects() {
+        let mut server = test_server();
+        assert_eq!("{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"hello\"}",
+                   server.handle_json_rpc("{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\",\"params\":[\"hello\"]}").unwrap());
+
+        let reply = server.handle_json_rpc("{\"jsonrpc\":\"2.0\",\"id\":\"a\",\"method\":\"find\",\"params\":{\"limit\":1,\"args\":\"SpawnActors\"}}").unwrap();
+        assert!(reply.starts_with("{\"jsonrpc\":\"2.0\",\"id\":\"a\",\"result\":[{\"type\":\"tag\",\"name\":\"SpawnActors\""));
+
+        assert_eq!("{\"jsonrpc\":\"2.0\",\"id\":2,\"error\":{\"code\":-32601,\"message\":\"Method not found: bogus\"}}",
+                   server.handle_json_rpc("{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"bogus\"}").unwrap());
+        assert_eq!("{\"jsonrpc\":\"2.0\",\"id\":3,\"error\":{\"code\":-32000,\"message\":\"Unknown tag kind 'bogus'\",\"data\":{\"kind\":\"parse\"}}}",
+                   server.handle_json_rpc("{\"jsonrpc\":\"2.0\",\"id\":3,\"method\":\"find\",\"params\":[\"kind:bogus\"]}").unwrap());
+        assert_eq!("{\"jsonrpc\":\"2.0\",\"id\":4,\"error\":{\"code\":-32602,\"message\":\"Unrecognized param 'bogus'; usage: echo [<text>...]\"}}",
+                   server.handle_json_rpc("{\"jsonrpc\":\"2.0\",\"id\":4,\"method\":\"echo\",\"params\":{\"bogus\":true}}").unwrap());
+        assert!(server.handle_json_rpc("{\"id\":4}").unwrap().contains("\"code\":-32600"));
+        assert!(server.handle_json_rpc("{").unwrap().contains("\"code\":-32700"));
+    }
+
+    #[test]
+    fn should_answer_batches_without_notifications() {
+        let mut server = test_server();
+        let batch = "[{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\",\"params\":[\"a\"]},\
+                     {\"jsonrpc\":\"2.0\",\"method\":\"echo\",\"params\":[\"b\"]},\
+                     {\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"echo\",\"params\":[\"c\"]}]";
+        assert_eq!("[{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"a\"},{\"jsonrpc\":\"2.0\",\"id\":2,\"result\":\"c\"}]",
+                   server.handle_json_rpc(batch).unwrap());
+        assert!(server.handle_json_rpc("[{\"jsonrpc\":\"2.0\",\"method\":\"echo\",\"params\":[\"b\"]}]").is_none());
+        assert!(server.handle_json_rpc("[]").unwrap().contains("\"code\":-32600"));
+    }
+}
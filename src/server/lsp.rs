@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+
+use serde_json;
+use serde_json::Value;
+
+use query::Query;
+use server::Server;
+use server::panic_message;
+use server::commands::other_file;
+use server::commands::rank_tags;
+use server::commands::resolve;
+use server::json_rpc;
+use server::json_rpc::COMMAND_ERROR;
+use server::json_rpc::INTERNAL_ERROR;
+use server::json_rpc::INVALID_PARAMS;
+use server::json_rpc::METHOD_NOT_FOUND;
+use server::json_rpc::PARSE_ERROR;
+use tags::TagDatabase;
+use tags::TagDefinition;
+use tags::TagKind;
+use tags::scope_parents;
+use tags::visit_history::DEFAULT_CLIENT;
+
+use VERSION;
+
+/// Workspace symbol requests reply with at most this many symbols.
+const MAX_WORKSPACE_SYMBOLS: usize = 100;
+
+/// The custom request for the header or source counterpart of a document.
+const OTHER_FILE_METHOD: &str = "enigma/otherFile";
+
+/// The state of a Language Server Protocol session: the workspace root, which relative paths
+/// in tags files are resolved against, the text of the documents open in the editor, and the
+/// client whose visits rank workspace symbols.
+struct LspSession {
+    root: Option<String>,
+    documents: HashMap<String, String>,
+    client: String,
+}
+
+type LspResult = Result<Value, (i64, String)>;
+
+impl<'a> Server<'a> {
+    /// Answers Language Server Protocol messages until the client sends `exit` or disconnects.
+    pub fn serve_lsp<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W) -> io::Result<()> {
+        let mut session = LspSession {
+            root: None,
+            documents: HashMap::new(),
+            client: DEFAULT_CLIENT.to_string(),
+        };
+
+        loop {
+            let message = match json_rpc::read_message(reader) {
+                Ok(Some(v)) => v,
+                Ok(None) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                    warn!("Invalid LSP message: {}", e);
+                    return json_rpc::write_message(writer, &json_rpc::invalid_message_response(e));
+                }
+                Err(e) => return Err(e),
+            };
+            let message: Value = match serde_json::from_str(&message) {
+                Ok(v) => v,
+                Err(e) => {
+                    let response = json!({"jsonrpc": "2.0", "id": null, "error": {"code": PARSE_ERROR, "message": format!("Parse error: {}", e)}});
+                    json_rpc::write_message(writer, &response.to_string())?;
+                    continue;
+                }
+            };
+
+            let method = message["method"].as_str().unwrap_or("");
+            if method == "exit" {
+                break;
+            }
+            info!("Handling LSP method \"{}\"", method);
+            let params = &message["params"];
+            let result = match panic::catch_unwind(AssertUnwindSafe(|| self.handle_lsp_method(&mut session, method, params))) {
+                Ok(result) => result,
+                Err(cause) => {
+                    let message = panic_message(&*cause);
+                    error!("LSP method \"{}\" panicked: {}", method, message);
+                    Err((INTERNAL_ERROR, format!("Request panicked: {}", message)))
+                }
+            };
+
+            // notifications get no response
+            let id = match message.get("id") {
+                None => continue,
+                Some(v) => v,
+            };
+            let response = match result {
+                Ok(v) => json!({"jsonrpc": "2.0", "id": id, "result": v}),
+                Err((code, e)) => json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": e}}),
+            };
+            json_rpc::write_message(writer, &response.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn handle_lsp_method(&mut self, session: &mut LspSession, method: &str, params: &Value) -> LspResult {
+        match method {
+            "initialize" => {
+                session.root = params["rootUri"].as_str().and_then(path_from_uri)
+                    .or_else(|| params["rootPath"].as_str().map(|v| v.to_string()));
+                if let Some(name) = params["clientInfo"]["name"].as_str().filter(|v| !v.is_empty() && !v.contains(char::is_control)) {
+                    session.client = name.to_string();
+                }
+                Ok(json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "workspaceSymbolProvider": true,
+                        "definitionProvider": true,
+                        "documentSymbolProvider": true,
+                        "experimental": {"otherFileProvider": true},
+                    },
+                    "serverInfo": {"name": "enigma", "version": VERSION},
+                }))
+            }
+            "initialized" | "shutdown" => Ok(Value::Null),
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                if let (Some(uri), Some(text)) = (document["uri"].as_str(), document["text"].as_str()) {
+                    session.documents.insert(uri.to_string(), text.to_string());
+                }
+                Ok(Value::Null)
+            }
+            "textDocument/didChange" => {
+                // documents are synced in full, so the last change holds the whole text
+                let text = params["contentChanges"].as_array().and_then(|v| v.last()).and_then(|v| v["text"].as_str());
+                if let (Some(uri), Some(text)) = (params["textDocument"]["uri"].as_str(), text) {
+                    session.documents.insert(uri.to_string(), text.to_string());
+                }
+                Ok(Value::Null)
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    session.documents.remove(uri);
+                }
+                Ok(Value::Null)
+            }
+            "workspace/symbol" => {
+                let query = params["query"].as_str().ok_or_else(|| invalid_params("query"))?;
+                workspace_symbols(&self.tag_database, session, query)
+            }
+            "textDocument/definition" => {
+                let uri = params["textDocument"]["uri"].as_str().ok_or_else(|| invalid_params("textDocument"))?;
+                let (line, character) = match (params["position"]["line"].as_u64(), params["position"]["character"].as_u64()) {
+                    (Some(line), Some(character)) => (line as usize, character as usize),
+                    _ => return Err(invalid_params("position")),
+                };
+                Ok(definition(&self.tag_database, session, uri, line, character))
+            }
+            "textDocument/documentSymbol" => {
+                let uri = params["textDocument"]["uri"].as_str().ok_or_else(|| invalid_params("textDocument"))?;
+                let file_path = path_from_uri(uri).ok_or_else(|| invalid_params("textDocument"))?;
+                document_symbols(&self.tag_database, &file_path)
+            }
+            OTHER_FILE_METHOD => {
+                let uri = params["textDocument"]["uri"].as_str().ok_or_else(|| invalid_params("textDocument"))?;
+                let file_path = path_from_uri(uri).ok_or_else(|| invalid_params("textDocument"))?;
+                Ok(match other_file(&self.tag_database, &file_path) {
+                    None => Value::Null,
+                    Some(v) => Value::String(session.uri_of(v)),
+                })
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        }
+    }
+}
+
+impl LspSession {
+    /// The URI of a path from a tags file. Relative paths are taken from the workspace root.
+    fn uri_of(&self, file_path: &str) -> String {
+        match self.root {
+            Some(ref root) if !is_absolute(file_path) => uri_from_path(&format!("{}/{}", root.trim_end_matches('/'), file_path)),
+            _ => uri_from_path(file_path),
+        }
+    }
+
+    /// The text of a line of a document, from the editor if it is open, else from disk.
+    fn line_text(&self, uri: &str, line: usize) -> Option<String> {
+        if let Some(text) = self.documents.get(uri) {
+            return text.lines().nth(line).map(|v| v.to_string());
+        }
+        let mut text = String::new();
+        File::open(path_from_uri(uri)?).and_then(|mut v| v.read_to_string(&mut text)).ok()?;
+        text.lines().nth(line).map(|v| v.to_string())
+    }
+
+    fn location(&self, tag: &TagDefinition) -> Value {
+        json!({"uri": self.uri_of(tag.source_file()), "range": name_range(tag)})
+    }
+}
+
+fn invalid_params(name: &str) -> (i64, String) {
+    (INVALID_PARAMS, format!("Missing or invalid param '{}'", name))
+}
+
+/// The best matches of the fuzzy finder for the query, as symbol information.
+fn workspace_symbols(tag_database: &TagDatabase, session: &LspSession, query: &str) -> LspResult {
+    let terms: Vec<&str> = query.split_whitespace().collect();
+    if terms.is_empty() {
+        return Ok(json!([]));
+    }
+    let query = Query::parse(&terms).map_err(|e| (INVALID_PARAMS, e))?;
+
+    let frecency = |tag: &TagDefinition| tag_database.frecency(&session.client, tag);
+    let preference = tag_database.preference(None);
+    let symbols: Vec<Value> = rank_tags(&query, &frecency, &preference, tag_database.all_definitions())
+        .into_iter()
+        .take(MAX_WORKSPACE_SYMBOLS)
+        .map(|(tag, _)| json!({
+            "name": tag.name(),
+            "kind": symbol_kind(tag),
+            "location": session.location(tag),
+            "containerName": tag.scope_name(),
+        }))
+        .collect();
+    Ok(Value::Array(symbols))
+}
+
+/// The location of the definition of the identifier at the position, or null.
+fn definition(tag_database: &TagDatabase, session: &LspSession, uri: &str, line: usize, character: usize) -> Value {
+    let (file_path, line_text) = match (path_from_uri(uri), session.line_text(uri, line)) {
+        (Some(file_path), Some(line_text)) => (file_path, line_text),
+        _ => return Value::Null,
+    };
+    let column = char_index(&line_text, character);
+    match resolve(tag_database, &file_path, line + 1, column, &line_text) {
+        None => Value::Null,
+        Some(tag) => session.location(tag),
+    }
+}
+
+/// The definitions in the file, nested by scope.
+fn document_symbols(tag_database: &TagDatabase, file_path: &str) -> LspResult {
+    let tags = tag_database.tags_in_file(file_path).map_err(|e| (COMMAND_ERROR, e))?;
+    let tags: Vec<&TagDefinition> = tags.into_iter().filter(|v| !v.is_reference()).collect();
+    let parents = scope_parents(&tags);
+    Ok(document_symbols_in(&tags, &parents, None))
+}
+
+fn document_symbols_in(tags: &[&TagDefinition], parents: &[Option<usize>], parent: Option<usize>) -> Value {
+    Value::Array((0..tags.len())
+        .filter(|&i| parents[i] == parent)
+        .map(|i| {
+            let tag = tags[i];
+            // the range ends at the start of the line after the tag's last line
+            let start_line = tag.source_line().saturating_sub(1);
+            let end_line = tag.end_line().unwrap_or_else(|| tag.source_line()).max(tag.source_line());
+            json!({
+                "name": tag.name(),
+                "detail": tag.signature(),
+                "kind": symbol_kind(tag),
+                "range": {"start": {"line": start_line, "character": 0}, "end": {"line": end_line, "character": 0}},
+                "selectionRange": name_range(tag),
+                "children": document_symbols_in(tags, parents, Some(i)),
+            })
+        })
+        .collect())
+}
+
+/// The range of the tag's name on its declaration line, or the start of the line if the name
+/// is not written there.
+fn name_range(tag: &TagDefinition) -> Value {
+    let line = tag.source_line().saturating_sub(1);
+    let declaration = tag.declaration();
+    let (start, end) = match declaration.find(tag.name()) {
+        None => (0, 0),
+        Some(v) => {
+            let start = declaration[..v].encode_utf16().count();
+            (start, start + tag.name().encode_utf16().count())
+        }
+    };
+    json!({"start": {"line": line, "character": start}, "end": {"line": line, "character": end}})
+}
+
+/// The LSP symbol kind of a tag.
+fn symbol_kind(tag: &TagDefinition) -> u32 {
+    match tag.kind() {
+        TagKind::File | TagKind::HeaderInclude => 1,
+        TagKind::Namespace | TagKind::NamespaceAlias | TagKind::NamespaceUsingStatement => 3,
+        TagKind::Class | TagKind::Typedef | TagKind::ForwardDeclaration => 5,
+        TagKind::FunctionDefinition | TagKind::FunctionPrototype if tag.is_method() => 6,
+        TagKind::ClassMember => 8,
+        TagKind::Enum => 10,
+        TagKind::FunctionDefinition | TagKind::FunctionPrototype => 12,
+        TagKind::MacroDefinitions => 14,
+        TagKind::GotoLabel => 20,
+        TagKind::EnumValue => 22,
+        TagKind::Struct | TagKind::Union => 23,
+        TagKind::Variable | TagKind::LocalVariable | TagKind::FunctionParameter | TagKind::Unknown => 13,
+    }
+}
+
+/// The index in characters of a position given in UTF-16 code units, as LSP counts them.
+fn char_index(line_text: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in line_text.chars().enumerate() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line_text.chars().count()
+}
+
+fn is_absolute(file_path: &str) -> bool {
+    let bytes = file_path.as_bytes();
+    Path::new(file_path).is_absolute() || file_path.starts_with('/') || file_path.starts_with('\\')
+        || (bytes.len() > 1 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+}
+
+/// The path of a `file://` URI, with Windows drive letters as `C:/...`.
+fn path_from_uri(uri: &str) -> Option<String> {
+    let encoded = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let hex = encoded.get(i + 1..i + 3).and_then(|v| u8::from_str_radix(v, 16).ok());
+        match hex {
+            Some(v) if encoded.as_bytes()[i] == b'%' => {
+                bytes.push(v);
+                i += 3;
+            }
+            _ => {
+                bytes.push(encoded.as_bytes()[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+
+    let has_drive = path.len() > 2 && path.as_bytes()[0] == b'/' && path.as_bytes()[1].is_ascii_alphabetic()
+        && path.as_bytes()[2] == b':';
+    Some(if has_drive { path[1..].to_string() } else { path })
+}
+
+fn uri_from_path(file_path: &str) -> String {
+    let path = file_path.replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~:".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+    use tags::TagFile;
+
+    fn test_server<'a>() -> Server<'a> {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Actor.h\tGame/Public/Actor.h\t1;\"\tF\tline:1",
+            "Actor.cpp\tGame/Private/Actor.cpp\t1;\"\tF\tline:1",
+            "AActor\tGame/Public/Actor.h\t/^class AActor {$/;\"\tc\tline:4\tend:9",
+            "Tick\tGame/Public/Actor.h\t/^\tvoid Tick(float Delta);$/;\"\tp\tline:6\tclass:AActor\tsignature:(float Delta)",
+            "Tick\tGame/Private/Actor.cpp\t/^void AActor::Tick(float Delta) {$/;\"\tf\tline:3\tclass:AActor\tend:6",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut server = Server::new();
+        server.tag_database.add_tag_file(tag_file);
+        server
+    }
+
+    /// The responses of the server to the messages, framed as a client would send them.
+    fn exchange(server: &mut Server, messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            json_rpc::write_message(&mut input, &message.to_string()).unwrap();
+        }
+        let mut output = Vec::new();
+        server.serve_lsp(&mut BufReader::new(&input[..]), &mut output).unwrap();
+
+        let mut reader = BufReader::new(&output[..]);
+        let mut responses = Vec::new();
+        while let Some(message) = json_rpc::read_message(&mut reader).unwrap() {
+            responses.push(serde_json::from_str(&message).unwrap());
+        }
+        responses
+    }
+
+    fn initialize() -> Value {
+        json!({"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {"rootUri": "file:///work/Engine"}})
+    }
+
+    #[test]
+    fn should_convert_uris_and_paths() {
+        assert_eq!(Some("C:/Game/My Actor.h".to_string()), path_from_uri("file:///C%3A/Game/My%20Actor.h"));
+        assert_eq!(Some("/work/Actor.h".to_string()), path_from_uri("file:///work/Actor.h"));
+        assert_eq!("file:///C:/Game/My%20Actor.h", uri_from_path("C:\\Game\\My Actor.h"));
+        assert_eq!(2, char_index("a\u{1F600}b", 3));
+    }
+
+    #[test]
+    fn should_initialize_and_exit() {
+        let mut server = test_server();
+        let responses = exchange(&mut server, &[
+            initialize(),
+            json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+            json!({"jsonrpc": "2.0", "id": 1, "method": "bogus/method"}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"}),
+        ]);
+        assert_eq!(3, responses.len());
+        assert_eq!(json!(true), responses[0]["result"]["capabilities"]["definitionProvider"]);
+        assert_eq!(json!(METHOD_NOT_FOUND), responses[1]["error"]["code"]);
+        assert_eq!(json!({"jsonrpc": "2.0", "id": 2, "result": null}), responses[2]);
+    }
+
+    #[test]
+    fn should_find_symbols_and_definitions() {
+        let mut server = test_server();
+        let responses = exchange(&mut server, &[
+            initialize(),
+            json!({"jsonrpc": "2.0", "id": 1, "method": "workspace/symbol", "params": {"query": "AAct"}}),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {"textDocument": {
+                "uri": "file:///work/Engine/Game/Private/Actor.cpp", "languageId": "cpp", "version": 1,
+                "text": "#include \"Actor.h\"\n\nvoid AActor::Tick(float Delta) {\n\tTick(Delta);\n}\n"}}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/definition", "params": {
+                "textDocument": {"uri": "file:///work/Engine/Game/Private/Actor.cpp"}, "position": {"line": 3, "character": 2}}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "enigma/otherFile", "params": {
+                "textDocument": {"uri": "file:///work/Engine/Game/Private/Actor.cpp"}}}),
+        ]);
+        assert_eq!(json!([{
+            "name": "AActor",
+            "kind": 5,
+            "location": {"uri": "file:///work/Engine/Game/Public/Actor.h",
+                         "range": {"start": {"line": 3, "character": 6}, "end": {"line": 3, "character": 12}}},
+            "containerName": null,
+        }]), responses[1]["result"]);
+        assert_eq!(json!({"uri": "file:///work/Engine/Game/Private/Actor.cpp",
+                          "range": {"start": {"line": 2, "character": 13}, "end": {"line": 2, "character": 17}}}),
+                   responses[2]["result"]);
+        assert_eq!(json!("file:///work/Engine/Game/Public/Actor.h"), responses[3]["result"]);
+    }
+
+    #[test]
+    fn should_nest_document_symbols() {
+        let mut server = test_server();
+        let responses = exchange(&mut server, &[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/documentSymbol", "params": {
+                "textDocument": {"uri": "file:///work/Engine/Game/Public/Actor.h"}}}),
+        ]);
+        let symbols = &responses[0]["result"];
+        assert_eq!(json!("AActor"), symbols[0]["name"]);
+        assert_eq!(json!({"start": {"line": 3, "character": 0}, "end": {"line": 9, "character": 0}}), symbols[0]["range"]);
+        assert_eq!(json!("Tick"), symbols[0]["children"][0]["name"]);
+        assert_eq!(json!("(float Delta)"), symbols[0]["children"][0]["detail"]);
+        assert_eq!(json!(6), symbols[0]["children"][0]["kind"]);
+        assert_eq!(1, symbols.as_array().unwrap().len());
+    }
+
+    #[test]
+    fn should_list_symbols_without_line_numbers() {
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("World.h\tGame/Public/World.h\t1;\"\tF\tline:1".to_string()));
+        tag_file.tags.push(TagDefinition::from_string("GWorld\tGame/Public/World.h\t/^extern UWorld* GWorld;$/;\"\tx".to_string()));
+        let mut server = Server::new();
+        server.tag_database.add_tag_file(tag_file);
+
+        let responses = exchange(&mut server, &[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/documentSymbol", "params": {
+                "textDocument": {"uri": "file:///work/Engine/Game/Public/World.h"}}}),
+        ]);
+        assert_eq!(json!("GWorld"), responses[0]["result"][0]["name"]);
+        assert_eq!(json!({"start": {"line": 0, "character": 0}, "end": {"line": 1, "character": 0}}), responses[0]["result"][0]["range"]);
+    }
+}
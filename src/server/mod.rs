@@ -1,27 +1,75 @@
 extern crate yaml_rust;
 
 pub mod commands;
+pub mod error;
+pub mod json_rpc;
+pub mod lsp;
+pub mod reply;
+pub mod transport;
 
+use std::any::Any;
+use std::collections::HashMap;
+use std::io;
 use std::io::prelude::*;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::fs;
 use std::fs::File;
-use std::net::TcpListener;
-use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
 
-use self::yaml_rust::{YamlLoader};
+use signal_hook::consts::SIGINT;
+use signal_hook::consts::SIGTERM;
+
+use self::yaml_rust::{Yaml, YamlLoader};
 
 use tags::TagDatabase;
+use tags::visit_history::VisitHistory;
 
 use self::commands::*;
+use self::error::CommandError;
+use self::reply::Reply;
+use self::reply::ReplyFormat;
+use self::reply::reply_format_from_str;
+use self::transport::Transport;
+use self::transport::TransportOverrides;
+use self::transport::transport_from_config;
+use VERSION;
+
+/// Where the visit history and the Unix socket are kept unless configured otherwise.
+pub const DEFAULT_DATA_DIRECTORY: &str = ".enigma";
 
 pub struct Server<'a> {
-    commands: Vec<Box<ServerCommand + 'a>>,
+    commands: Vec<Box<dyn ServerCommand + 'a>>,
+    /// The index of the command registered under each name and alias.
+    command_names: HashMap<&'static str, usize>,
+    /// The commands answered by the server itself rather than from the tag database.
+    builtin_commands: Vec<CommandSpec>,
     tag_database: TagDatabase,
     command_index: usize,
+    default_format: ReplyFormat,
+    format: ReplyFormat,
+    started: Instant,
+    connection_count: usize,
+    /// Set by the `shutdown` command or a signal. The server stops once the request being
+    /// answered is done.
+    shutdown: Arc<AtomicBool>,
 }
 
-pub trait ServerCommand {
-    fn can_execute(&self, command: &str) -> bool;
-    fn execute(&self, command: &str, tag_database: &mut TagDatabase) -> String;
+pub trait ServerCommand: Send {
+    /// The name, aliases, summary, options and arguments of the command.
+    fn spec(&self) -> &CommandSpec;
+    fn run(&self, args: &CommandArgs, tag_database: &mut TagDatabase) -> Result<Reply, CommandError>;
+
+    /// Runs the request if it matches the spec, else fails with the usage of the command.
+    #[cfg(test)]
+    fn execute(&self, request: &str, tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+        let args = self.spec().parse(request)?;
+        self.run(&args, tag_database)
+    }
 }
 
 impl <'a> Server<'a> {
@@ -29,87 +77,417 @@ impl <'a> Server<'a> {
         Server {
             tag_database: TagDatabase::new(),
             commands: Vec::new(),
+            command_names: HashMap::new(),
+            builtin_commands: vec![
+                CommandSpec::new("help", "List the commands, or describe one").optional_argument("command"),
+                CommandSpec::new("format", "Set the reply format for the rest of the connection").argument("elisp|json"),
+                CommandSpec::new("ping", "Check that the server is running"),
+                CommandSpec::new("status", "Describe the server and the loaded tags files"),
+                CommandSpec::new("shutdown", "Stop the server once the current request is answered"),
+            ],
             command_index: 0,
+            default_format: ReplyFormat::Elisp,
+            format: ReplyFormat::Elisp,
+            started: Instant::now(),
+            connection_count: 0,
+            shutdown: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Serves requests on the transport of the config file, as overridden from the command line,
+    /// until the server shuts down.
+    pub fn start_with_config_file(config_file: &str, overrides: &TransportOverrides) -> Result<(), String> {
+        let (mut server, configured_transport) = Server::from_config_file(config_file)?;
+        let transport = overrides.apply(configured_transport);
+        server.stop_on_signals();
+        let result = server.listen(&transport)
+            .map_err(|e| format!("Failed to serve requests on {}: {}", transport, e));
+        info!("Shut down after {} connections", server.connection_count);
+        ::log::logger().flush();
+        result
+    }
+
+    /// Stops the server on SIGINT and SIGTERM as if it was sent `shutdown`. A second signal
+    /// exits right away, e.g. while waiting for a request on stdin.
+    fn stop_on_signals(&self) {
+        for &signal in &[SIGINT, SIGTERM] {
+            let result = signal_hook::flag::register_conditional_shutdown(signal, 1, self.shutdown.clone())
+                .and_then(|_| signal_hook::flag::register(signal, self.shutdown.clone()));
+            if let Err(e) = result {
+                warn!("Failed to handle signal {}: {}", signal, e);
+            }
         }
     }
 
-    pub fn start_with_config_file(config_file: &str) {
-        let mut file = File::open(config_file).unwrap();
-        let mut config_contents = String::new();
-        file.read_to_string(&mut config_contents).unwrap();
-        let docs = YamlLoader::load_from_str(&config_contents.as_str()).unwrap();
+    /// Whether the server was asked to stop.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
 
-        let doc = &docs[0];
+    /// Serves the Language Server Protocol over stdin and stdout instead of listening for TCP
+    /// connections.
+    pub fn start_lsp_with_config_file(config_file: &str) -> Result<(), String> {
+        let (mut server, _) = Server::from_config_file(config_file)?;
+        let stdin = io::stdin();
+        server.serve_lsp(&mut stdin.lock(), &mut io::stdout())
+            .map_err(|e| format!("LSP connection failed: {}", e))
+    }
+
+    /// The server set up from the config file, along with the transport to serve it on.
+    fn from_config_file(config_file: &str) -> Result<(Server<'a>, Transport), String> {
+        let doc = &read_config_file(config_file)?;
 
         // config
-        let hostname = doc["config"]["hostname"].as_str().or_else(|| Some("localhost")).unwrap();
-        let port = doc["config"]["port"].as_i64().or_else(|| Some(9092)).unwrap() as usize;
+        let include_roots = match doc["config"]["include_roots"].as_vec() {
+            None => HeaderFor::default_include_roots(),
+            Some(v) => v.iter().filter_map(|root| root.as_str()).map(|root| root.to_string()).collect(),
+        };
+        let index_declarations = doc["config"]["index_declarations"].as_bool().unwrap_or(false);
+        let data_directory = doc["config"]["data_directory"].as_str().unwrap_or(DEFAULT_DATA_DIRECTORY);
+        let transport = transport_from_config(&doc["config"], data_directory);
+        let format = doc["config"]["format"].as_str().map(|v| match reply_format_from_str(v) {
+            Some(format) => format,
+            None => {
+                warn!("Unknown reply format {}, using elisp", v);
+                ReplyFormat::Elisp
+            }
+        });
 
         let mut server = Server::new();
+        server.set_default_format(format.unwrap_or(ReplyFormat::Elisp));
+        server.tag_database.set_index_declarations(index_declarations);
+        if let Err(e) = fs::create_dir_all(data_directory) {
+            warn!("Failed to create data directory {}: {}", data_directory, e);
+        }
+        server.tag_database.set_visit_history(VisitHistory::load(Path::new(data_directory).join("history")));
         server.add_command(Box::new(Echo::new()));
         server.add_command(Box::new(FindOtherFile::new()));
         server.add_command(Box::new(LoadTagsFile::new()));
         server.add_command(Box::new(DescribeTag::new()));
         server.add_command(Box::new(FindTags::new()));
+        server.add_command(Box::new(Overrides::new()));
+        server.add_command(Box::new(Overridden::new()));
+        server.add_command(Box::new(GotoDefinition::new()));
+        server.add_command(Box::new(GotoDeclaration::new()));
+        server.add_command(Box::new(Outline::new()));
+        server.add_command(Box::new(ScopeAt::new()));
+        server.add_command(Box::new(Resolve::new()));
+        server.add_command(Box::new(References::new()));
+        server.add_command(Box::new(Includes::new()));
+        server.add_command(Box::new(IncludedBy::new()));
+        server.add_command(Box::new(IncludePath::new()));
+        server.add_command(Box::new(HeaderFor::new(include_roots)));
+        server.add_command(Box::new(GrepTags::new()));
+        server.add_command(Box::new(Visited::new()));
+
+        // tags files, each either a path or a path with a priority
+        if let Some(tags_files) = doc["tags_files"].as_vec() {
+            for tags_file in tags_files {
+                let path = tags_file.as_str().or_else(|| tags_file["path"].as_str());
+                let priority = tags_file["priority"].as_i64().unwrap_or(0);
+                match path {
+                    Some(path) => { server.execute(&format!("load-tags-file -p {} \"{}\"", priority, path)); }
+                    None => warn!("Ignoring tags file entry without a path"),
+                }
+            }
+        }
 
         // commands
-        for command in doc["commands"].as_vec().unwrap() {
-            server.execute(command.as_str().unwrap());
+        for command in doc["commands"].as_vec().unwrap_or(&Vec::new()) {
+            match command.as_str() {
+                Some(command) => { server.execute(command); }
+                None => warn!("Ignoring command which is not a string: {:?}", command),
+            }
         }
 
-        server.bind(hostname, port);
+        Ok((server, transport))
     }
     
-    pub fn add_command(&mut self, command: Box<ServerCommand>) {
+    /// Registers the command under its name and aliases. Names already taken are skipped.
+    pub fn add_command(&mut self, command: Box<dyn ServerCommand + 'a>) {
+        let index = self.commands.len();
+        let spec = command.spec();
+        for &name in Some(&spec.name()).into_iter().chain(spec.aliases()) {
+            if self.command_names.contains_key(name) || self.builtin(name).is_some() {
+                warn!("Command name {} is already taken", name);
+                continue;
+            }
+            self.command_names.insert(name, index);
+        }
         self.commands.push(command);
     }
 
-    pub fn bind(&mut self, hostname: &str, port: usize) {
-        let listener = TcpListener::bind(format!("{}:{}", hostname, port)).unwrap();
+    fn command(&self, name: &str) -> Option<&(dyn ServerCommand + 'a)> {
+        self.command_names.get(name).map(|&v| &*self.commands[v])
+    }
 
-        info!("Listening for TCP connections on {}:{}", hostname, port);
+    fn builtin(&self, name: &str) -> Option<&CommandSpec> {
+        self.builtin_commands.iter().find(|v| v.name() == name)
+    }
 
-        for stream in listener.incoming() {
-            let stream = stream.unwrap();
+    /// The spec of the command or built-in command registered under `name`.
+    fn command_spec(&self, name: &str) -> Option<&CommandSpec> {
+        self.command(name).map(|v| v.spec()).or_else(|| self.builtin(name))
+    }
 
-            self.handle_connection(stream);
-        }
+    /// The format of replies on new connections, until a `format` request changes it.
+    pub fn set_default_format(&mut self, format: ReplyFormat) {
+        self.default_format = format;
+        self.format = format;
     }
 
+    /// Executes the request and writes the reply in the format of the connection. Requests may
+    /// be prefixed with `--format <elisp|json>` to pick the format of their own reply, and the
+    /// `format <elisp|json>` request changes the format for the rest of the connection.
     pub fn execute(&mut self, request: &str) -> String {
         let command_index = self.command_index;
         self.command_index += 1;
-        
+
         info!("Executing command [{}]: \"{}\"", command_index, request);
-        let mut handled = false;
-        let mut result = String::new();
-        
-        for command in &self.commands {
-            if command.can_execute(request) {
-                let reply = command.execute(&request, &mut self.tag_database);
-                result = reply;
-                handled = true;
-                break;
+        let (format, reply) = match split_format_option(request) {
+            Err(e) => (self.format, Err(e)),
+            Ok((format, request)) => (format.unwrap_or(self.format), self.execute_request(request)),
+        };
+        let result = match reply {
+            Ok(reply) => reply.serialize(format),
+            Err(e) => {
+                warn!("Command failed [{}]: {}", command_index, e);
+                e.to_reply().serialize(format)
+            }
+        };
+
+        debug!("Command result [{}]: \"{}\"", command_index, result);
+        result
+    }
+
+    fn execute_request(&mut self, request: &str) -> Result<Reply, CommandError> {
+        let name = request.split_whitespace().next().unwrap_or("");
+        let args = match self.command_spec(name) {
+            None => return Err(CommandError::NotFound(format!("Unrecognized command '{}'", name))),
+            Some(spec) => spec.parse(request)?,
+        };
+        self.execute_command(name, &args)
+    }
+
+    /// Runs the command `name`, failing with an internal error rather than taking the server
+    /// down if the command panics. Commands are expected to fail with errors instead; catching
+    /// the panic is only a last resort for bugs, which leaves the server running. A command
+    /// modifying the tag database may have left it half changed though, so then the server
+    /// shuts down and the panic goes on.
+    fn execute_command(&mut self, name: &str, args: &CommandArgs) -> Result<Reply, CommandError> {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.dispatch(name, args))) {
+            Ok(result) => result,
+            Err(cause) => {
+                let message = panic_message(&*cause);
+                error!("Command panicked: {}", message);
+                if self.command_spec(name).is_some_and(|v| v.is_modifying()) {
+                    self.shutdown.store(true, Ordering::SeqCst);
+                    panic::resume_unwind(cause);
+                }
+                Err(CommandError::Internal(format!("Command panicked: {}", message)))
             }
         }
+    }
 
-        if !handled {
-            result = "Unrecognized command".to_string();
+    fn dispatch(&mut self, name: &str, args: &CommandArgs) -> Result<Reply, CommandError> {
+        if self.builtin(name).is_some() {
+            return self.execute_builtin(name, args);
         }
 
-        info!("Command result [{}]: \"{}\"", command_index, result);
-        result
+        match self.command_names.get(name) {
+            None => Err(CommandError::NotFound(format!("Unrecognized command '{}'", name))),
+            Some(&index) => self.commands[index].run(args, &mut self.tag_database),
+        }
+    }
+
+    fn execute_builtin(&mut self, name: &str, args: &CommandArgs) -> Result<Reply, CommandError> {
+        Ok(match name {
+            "format" => match reply_format_from_str(args.arg(0)) {
+                None => return Err(args.usage_error()),
+                Some(format) => {
+                    self.format = format;
+                    Reply::True
+                }
+            },
+            "ping" => Reply::symbol("pong"),
+            "status" => self.status(),
+            "shutdown" => {
+                info!("Shutting down");
+                self.shutdown.store(true, Ordering::SeqCst);
+                Reply::True
+            }
+            _ => match args.optional_arg(0) {
+                None => Reply::list(self.commands.iter().map(|v| v.spec())
+                                    .chain(self.builtin_commands.iter())
+                                    .map(|v| v.summary_reply())),
+                Some(command_name) => {
+                    match self.command_spec(command_name) {
+                        None => return Err(CommandError::NotFound(format!("Unrecognized command '{}'", command_name))),
+                        Some(spec) => spec.help_reply(),
+                    }
+                }
+            },
+        })
+    }
+
+    fn status(&self) -> Reply {
+        let tags_files = self.tag_database.tag_files.iter().map(|v| {
+            Reply::record("tags-file")
+                .with("path", v.path())
+                .with("priority", Reply::Integer(i64::from(v.priority)))
+                .with("tags", v.tags.len())
+                .with("load-ms", Reply::Integer(v.load_duration.as_millis() as i64))
+        });
+        Reply::record("status")
+            .with("version", VERSION)
+            .with("uptime", Reply::Integer(self.started.elapsed().as_secs() as i64))
+            .with("connections", self.connection_count)
+            .with("tags-files", Reply::list(tags_files))
+    }
+}
+
+/// The message a panic was raised with.
+fn panic_message(cause: &(dyn Any + Send)) -> String {
+    cause.downcast_ref::<&str>().map(|v| v.to_string())
+        .or_else(|| cause.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown cause".to_string())
+}
+
+/// The first document of the YAML config file.
+pub fn read_config_file(config_file: &str) -> Result<Yaml, String> {
+    let mut config_contents = String::new();
+    File::open(config_file).and_then(|mut v| v.read_to_string(&mut config_contents))
+        .map_err(|e| format!("Failed to read config file {}: {}", config_file, e))?;
+    let mut docs = YamlLoader::load_from_str(config_contents.as_str())
+        .map_err(|e| format!("Failed to parse config file {}: {}", config_file, e))?;
+    Ok(if docs.is_empty() { Yaml::Null } else { docs.remove(0) })
+}
+
+/// Splits the `--format <name>` prefix off a request.
+fn split_format_option(request: &str) -> Result<(Option<ReplyFormat>, &str), CommandError> {
+    let rest = match request.strip_prefix("--format ") {
+        None => return Ok((None, request)),
+        Some(v) => v.trim_start(),
+    };
+    let (name, command) = match rest.find(' ') {
+        None => (rest, ""),
+        Some(v) => (&rest[..v], rest[v + 1..].trim_start()),
+    };
+    match reply_format_from_str(name) {
+        None => Err(CommandError::Usage(format!("Unknown reply format '{}'", name))),
+        Some(format) => Ok((Some(format), command)),
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagDefinition;
+    use tags::TagFile;
+
+    struct PanicCommand {
+        spec: CommandSpec,
+    }
+
+    impl ServerCommand for PanicCommand {
+        fn spec(&self) -> &CommandSpec {
+            &self.spec
+        }
 
-    fn handle_connection(&mut self, mut stream: TcpStream) {
-        let mut buffer = [0; 512];
-        stream.read(&mut buffer).unwrap();
+        fn run(&self, _args: &CommandArgs, _tag_database: &mut TagDatabase) -> Result<Reply, CommandError> {
+            panic!("out of cheese")
+        }
+    }
 
-        let request_raw_string = String::from_utf8(Vec::from(&buffer[..])).unwrap();
-        let request = request_raw_string.lines().nth(0).unwrap();
-        let reply = self.execute(request);
+    fn test_server<'a>() -> Server<'a> {
+        let mut server = Server::new();
+        server.add_command(Box::new(Echo::new()));
+        server
+    }
+
+    #[test]
+    fn should_pick_format_per_request() {
+        let mut server = test_server();
+        assert_eq!("\"hello\"", server.execute("echo hello"));
+        assert_eq!("\"hello\"", server.execute("--format json echo hello"));
+        assert_eq!("{\"type\":\"error\",\"kind\":\"not-found\",\"message\":\"Unrecognized command 'bogus'\"}", server.execute("--format json bogus"));
+        assert_eq!("(error :kind usage :message \"Unknown reply format 'xml'\")", server.execute("--format xml echo hello"));
+    }
+
+    #[test]
+    fn should_dispatch_by_exact_name_or_alias() {
+        let mut server = test_server();
+        server.add_command(Box::new(FindOtherFile::new()));
+        assert_eq!("(error :kind not-found :message \"Unrecognized command 'echoes'\")", server.execute("echoes hello"));
+        assert_eq!("(error :kind usage :message \"usage: find-other-file <file>\")", server.execute("other-file"));
+        assert_eq!("nil", server.execute("other-file Actor.h"));
+    }
+
+    #[test]
+    fn should_split_non_ascii_and_quoted_arguments() {
+        let mut server = test_server();
+        assert_eq!("\"héllo wörld\"", server.execute("echo héllo wörld"));
+        assert_eq!("(error :kind parse :message \"Unbalanced quote in request echo \\\"\")", server.execute("echo \""));
+    }
+
+    #[test]
+    fn should_generate_help() {
+        let mut server = test_server();
+        assert_eq!("((command :name \"echo\" :summary \"Reply with the arguments\") \
+                    (command :name \"help\" :summary \"List the commands, or describe one\") \
+                    (command :name \"format\" :summary \"Set the reply format for the rest of the connection\") \
+                    (command :name \"ping\" :summary \"Check that the server is running\") \
+                    (command :name \"status\" :summary \"Describe the server and the loaded tags files\") \
+                    (command :name \"shutdown\" :summary \"Stop the server once the current request is answered\"))",
+                   server.execute("help"));
+        assert_eq!("(command :name \"echo\" :aliases nil :summary \"Reply with the arguments\" :usage \"echo [<text>...]\" :options nil)",
+                   server.execute("help echo"));
+        assert_eq!("(error :kind not-found :message \"Unrecognized command 'bogus'\")", server.execute("help bogus"));
+        assert_eq!("(error :kind usage :message \"usage: format <elisp|json>\")", server.execute("format xml"));
+    }
+
+    #[test]
+    fn should_keep_format_for_the_connection() {
+        let mut server = test_server();
+        assert_eq!("t", server.execute("format json"));
+        assert_eq!("{\"type\":\"error\",\"kind\":\"not-found\",\"message\":\"Unrecognized command 'bogus'\"}", server.execute("bogus"));
+        assert_eq!("(error :kind not-found :message \"Unrecognized command 'bogus'\")", server.execute("--format elisp bogus"));
+    }
+
+    #[test]
+    fn should_survive_panicking_commands() {
+        let mut server = test_server();
+        server.add_command(Box::new(PanicCommand { spec: CommandSpec::new("panic", "Panic") }));
+        assert_eq!("(error :kind internal :message \"Command panicked: out of cheese\")", server.execute("panic"));
+        assert_eq!("\"hello\"", server.execute("echo hello"));
+    }
+
+    #[test]
+    fn should_shut_down_on_panics_modifying_tags() {
+        let mut server = test_server();
+        server.add_command(Box::new(PanicCommand { spec: CommandSpec::new("panic", "Panic").modifying() }));
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| server.execute("panic"))).is_err());
+        assert!(server.is_shutting_down());
+    }
+
+    #[test]
+    fn should_report_status() {
+        let mut server = test_server();
+        let mut tag_file = TagFile::new();
+        tag_file.tags.push(TagDefinition::from_string("Tick\tActor.h\t/^\tvoid Tick();$/;\"\tp\tline:12".to_string()));
+        server.tag_database.add_tag_file(tag_file);
+
+        assert_eq!("pong", server.execute("ping"));
+        assert_eq!(format!("(status :version \"{}\" :uptime 0 :connections 0 \
+                            :tags-files ((tags-file :path \"\" :priority 0 :tags 1 :load-ms 0)))", VERSION),
+                   server.execute("status"));
+    }
 
-        stream.write(reply.as_bytes()).unwrap();
-        stream.flush().unwrap();
+    #[test]
+    fn should_shut_down_on_request() {
+        let mut server = test_server();
+        assert!(!server.is_shutting_down());
+        assert_eq!("t", server.execute("shutdown"));
+        assert!(server.is_shutting_down());
     }
 }
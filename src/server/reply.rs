@@ -0,0 +1,262 @@
+use serde_json::Map;
+use serde_json::Value;
+
+use tags::TagDefinition;
+use tags::tag_kind_name;
+
+/// How replies are written to the client.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReplyFormat {
+    Elisp,
+    Json,
+}
+
+pub fn reply_format_from_str(format: &str) -> Option<ReplyFormat> {
+    match &format.to_lowercase()[..] {
+        "elisp" | "sexp" => Some(ReplyFormat::Elisp),
+        "json" => Some(ReplyFormat::Json),
+        _ => None,
+    }
+}
+
+/// The data a command replies with, independent of how it is written to the client.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Reply {
+    Nil,
+    True,
+    Integer(i64),
+    String(String),
+    Symbol(String),
+    List(Vec<Reply>),
+    /// A named set of properties, e.g. `(tag :name "Tick" :line 12)`.
+    Record(String, Vec<(String, Reply)>),
+}
+
+impl Reply {
+    pub fn string<S: Into<String>>(value: S) -> Reply {
+        Reply::String(value.into())
+    }
+
+    pub fn symbol<S: Into<String>>(value: S) -> Reply {
+        Reply::Symbol(value.into())
+    }
+
+    pub fn list<I: IntoIterator<Item = Reply>>(items: I) -> Reply {
+        Reply::List(items.into_iter().collect())
+    }
+
+    pub fn record(name: &str) -> Reply {
+        Reply::Record(name.to_string(), Vec::new())
+    }
+
+    /// Adds a property to a record. Other replies are returned unchanged.
+    pub fn with<T: Into<Reply>>(self, key: &str, value: T) -> Reply {
+        match self {
+            Reply::Record(name, mut properties) => {
+                properties.push((key.to_string(), value.into()));
+                Reply::Record(name, properties)
+            }
+            other => other,
+        }
+    }
+
+    /// The location and description of a tag.
+    pub fn tag(tag: &TagDefinition) -> Reply {
+        Reply::record("tag")
+            .with("name", tag.name())
+            .with("file", tag.source_file())
+            .with("line", tag.source_line())
+            .with("kind", Reply::symbol(tag_kind_name(&tag.kind())))
+            .with("scope", tag.scope_name())
+            .with("signature", tag.signature())
+    }
+
+    pub fn tags(tags: &[&TagDefinition]) -> Reply {
+        Reply::list(tags.iter().map(|v| Reply::tag(v)))
+    }
+
+    pub fn serialize(&self, format: ReplyFormat) -> String {
+        match format {
+            ReplyFormat::Elisp => self.to_elisp(),
+            ReplyFormat::Json => self.to_json(),
+        }
+    }
+
+    /// Writes the reply as an s-expression the client can `read`. Records become lists starting
+    /// with their name followed by a property list, and empty lists are `nil`. Newlines in
+    /// strings are escaped, so the reply fits on one line.
+    pub fn to_elisp(&self) -> String {
+        let mut result = String::new();
+        self.write_elisp(&mut result);
+        result
+    }
+
+    fn write_elisp(&self, out: &mut String) {
+        match *self {
+            Reply::Nil => out.push_str("nil"),
+            Reply::True => out.push('t'),
+            Reply::Integer(v) => out.push_str(&v.to_string()),
+            Reply::String(ref v) => {
+                out.push('"');
+                for c in v.chars() {
+                    match c {
+                        '"' | '\\' => {
+                            out.push('\\');
+                            out.push(c);
+                        }
+                        // keeps every reply on a single line
+                        '\n' => out.push_str("\\n"),
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+            }
+            Reply::Symbol(ref v) => write_elisp_symbol(v, out),
+            Reply::List(ref items) => {
+                if items.is_empty() {
+                    out.push_str("nil");
+                    return;
+                }
+                out.push('(');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    item.write_elisp(out);
+                }
+                out.push(')');
+            }
+            Reply::Record(ref name, ref properties) => {
+                out.push('(');
+                write_elisp_symbol(name, out);
+                for (key, value) in properties {
+                    out.push_str(" :");
+                    write_elisp_symbol(key, out);
+                    out.push(' ');
+                    value.write_elisp(out);
+                }
+                out.push(')');
+            }
+        }
+    }
+}
+
+impl Reply {
+    /// Writes the reply as JSON, see `to_json_value`.
+    pub fn to_json(&self) -> String {
+        self.to_json_value().to_string()
+    }
+
+    /// The reply as a JSON value. Records become objects whose `type` is the record name, nil is
+    /// `null` and symbols are strings. The record name wins over a property named `type`, and
+    /// the first of repeated properties is kept, so objects never hold duplicate keys.
+    pub fn to_json_value(&self) -> Value {
+        match *self {
+            Reply::Nil => Value::Null,
+            Reply::True => Value::Bool(true),
+            Reply::Integer(v) => Value::from(v),
+            Reply::String(ref v) | Reply::Symbol(ref v) => Value::String(v.clone()),
+            Reply::List(ref items) => Value::Array(items.iter().map(Reply::to_json_value).collect()),
+            Reply::Record(ref name, ref properties) => {
+                let mut object = Map::new();
+                object.insert("type".to_string(), Value::String(name.clone()));
+                for (key, value) in properties {
+                    object.entry(key.clone()).or_insert_with(|| value.to_json_value());
+                }
+                Value::Object(object)
+            }
+        }
+    }
+}
+
+/// Escapes the characters the reader would otherwise take as the end of the symbol.
+fn write_elisp_symbol(symbol: &str, out: &mut String) {
+    for c in symbol.chars() {
+        if !(c.is_alphanumeric() || "-+=*/_~!@$%^&:<>{}?.".contains(c)) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+impl<'a> From<&'a str> for Reply {
+    fn from(value: &'a str) -> Reply {
+        Reply::string(value)
+    }
+}
+
+impl From<String> for Reply {
+    fn from(value: String) -> Reply {
+        Reply::String(value)
+    }
+}
+
+impl From<usize> for Reply {
+    fn from(value: usize) -> Reply {
+        Reply::Integer(value as i64)
+    }
+}
+
+impl From<bool> for Reply {
+    fn from(value: bool) -> Reply {
+        if value { Reply::True } else { Reply::Nil }
+    }
+}
+
+impl<T: Into<Reply>> From<Option<T>> for Reply {
+    fn from(value: Option<T>) -> Reply {
+        match value {
+            None => Reply::Nil,
+            Some(v) => v.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_escape_strings_and_symbols() {
+        assert_eq!("\"C:\\\\Game\\\\\\\"Quoted\\\".h\"", Reply::string("C:\\Game\\\"Quoted\".h").to_elisp());
+        assert_eq!("operator\\ \\(\\)", Reply::symbol("operator ()").to_elisp());
+        assert_eq!("\"First\\nSecond\"", Reply::string("First\nSecond").to_elisp());
+    }
+
+    #[test]
+    fn should_write_records_as_property_lists() {
+        let reply = Reply::list(vec![
+            Reply::record("entry").with("line", 12).with("signature", None::<&str>).with("children", Reply::list(vec![])),
+            Reply::True,
+        ]);
+        assert_eq!("((entry :line 12 :signature nil :children nil) t)", reply.to_elisp());
+    }
+
+    #[test]
+    fn should_write_json() {
+        let reply = Reply::list(vec![
+            Reply::record("entry").with("line", 12).with("signature", None::<&str>).with("children", Reply::list(vec![])),
+            Reply::True,
+            Reply::string("Tab\tand \"quote\"\u{1}"),
+        ]);
+        assert_eq!("[{\"type\":\"entry\",\"line\":12,\"signature\":null,\"children\":[]},true,\"Tab\\tand \\\"quote\\\"\\u0001\"]",
+                   reply.to_json());
+    }
+
+    #[test]
+    fn should_not_write_duplicate_json_keys() {
+        let reply = Reply::record("entry").with("type", "class").with("line", 12).with("line", 13);
+        assert_eq!("{\"type\":\"entry\",\"line\":12}", reply.to_json());
+    }
+
+    #[test]
+    fn should_describe_tags() {
+        let tag = TagDefinition::from_string("Tick\tGame\\Actor.h\t/^\tvoid Tick(float \"Delta\");$/;\"\tp\tline:12\tclass:AActor\tsignature:(float \"Delta\")".to_string());
+        assert_eq!("(tag :name \"Tick\" :file \"Game\\\\Actor.h\" :line 12 :kind function_prototype :scope \"AActor\" \
+                    :signature \"(float \\\"Delta\\\")\")",
+                   Reply::tag(&tag).to_elisp());
+        assert_eq!("{\"type\":\"tag\",\"name\":\"Tick\",\"file\":\"Game\\\\Actor.h\",\"line\":12,\"kind\":\"function_prototype\",\
+                    \"scope\":\"AActor\",\"signature\":\"(float \\\"Delta\\\")\"}",
+                   Reply::tag(&tag).serialize(ReplyFormat::Json));
+    }
+}
@@ -0,0 +1,549 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use server::Server;
+use server::error::CommandError;
+use server::json_rpc;
+use server::yaml_rust::Yaml;
+
+/// Where the server takes requests from.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Transport {
+    /// One request per connection.
+    Tcp { hostname: String, port: usize },
+    /// One request per connection, from the users the socket file's mode lets connect.
+    Unix { path: PathBuf, mode: u32 },
+    /// Requests read from stdin, one per line, each answered with a line on stdout.
+    Stdio,
+}
+
+pub const DEFAULT_HOSTNAME: &str = "localhost";
+pub const DEFAULT_PORT: usize = 9092;
+
+/// Only the user running the server may connect to its socket by default.
+pub const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+/// How often a listener with no pending connections checks whether the server is shutting down.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often a connection waiting for a request checks whether the server is shutting down.
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Text requests longer than this many bytes are answered with an error rather than read into
+/// memory.
+const MAX_REQUEST_LENGTH: usize = 1024 * 1024;
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Transport::Tcp { ref hostname, port } => write!(f, "TCP {}:{}", hostname, port),
+            Transport::Unix { ref path, mode } => write!(f, "Unix socket {} (mode {:o})", path.display(), mode),
+            Transport::Stdio => write!(f, "stdio"),
+        }
+    }
+}
+
+/// The transport from the `config` section: `transport` is `tcp`, using `hostname` and `port`,
+/// or `unix`, using `socket_path`, by default in the data directory, and `socket_mode`, the
+/// octal permissions of the socket file such as `660`.
+pub fn transport_from_config(config: &Yaml, data_directory: &str) -> Transport {
+    let tcp = Transport::Tcp {
+        hostname: config["hostname"].as_str().unwrap_or(DEFAULT_HOSTNAME).to_string(),
+        port: config["port"].as_i64().map(|v| v as usize).unwrap_or(DEFAULT_PORT),
+    };
+
+    match config["transport"].as_str().unwrap_or("tcp") {
+        "tcp" => tcp,
+        "unix" => {
+            let path = match config["socket_path"].as_str() {
+                None => Path::new(data_directory).join("enigma.sock"),
+                Some(v) => PathBuf::from(v),
+            };
+            let mode_digits = config["socket_mode"].as_str().map(|v| v.to_string())
+                .or_else(|| config["socket_mode"].as_i64().map(|v| v.to_string()));
+            let mode = match mode_digits.map(|v| u32::from_str_radix(&v, 8)) {
+                None => DEFAULT_SOCKET_MODE,
+                Some(Ok(v)) => v,
+                Some(Err(_)) => {
+                    warn!("Invalid socket mode, using {:o}", DEFAULT_SOCKET_MODE);
+                    DEFAULT_SOCKET_MODE
+                }
+            };
+            Transport::Unix { path, mode }
+        }
+        other => {
+            warn!("Unknown transport {}, using tcp", other);
+            tcp
+        }
+    }
+}
+
+/// Transport settings from the command line, which take precedence over the config file.
+#[derive(Debug, Default)]
+pub struct TransportOverrides {
+    pub stdio: bool,
+    pub hostname: Option<String>,
+    pub port: Option<usize>,
+}
+
+impl TransportOverrides {
+    /// The configured transport with the overrides applied. A host or port serves over TCP
+    /// even when a Unix socket is configured.
+    pub fn apply(&self, configured: Transport) -> Transport {
+        if self.stdio {
+            return Transport::Stdio;
+        }
+        if self.hostname.is_none() && self.port.is_none() {
+            return configured;
+        }
+
+        let (hostname, port) = match configured {
+            Transport::Tcp { hostname, port } => (hostname, port),
+            _ => (DEFAULT_HOSTNAME.to_string(), DEFAULT_PORT),
+        };
+        Transport::Tcp {
+            hostname: self.hostname.clone().unwrap_or(hostname),
+            port: self.port.unwrap_or(port),
+        }
+    }
+}
+
+/// Stdin and stdout as a single stream.
+struct StdioStream {
+    stdin: io::Stdin,
+    stdout: io::Stdout,
+}
+
+impl Read for StdioStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdin.read(buf)
+    }
+}
+
+impl Write for StdioStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+/// A connection whose reads time out, which ends as if the client disconnected once the server
+/// shuts down rather than keeping the server waiting for an idle client.
+struct Connection<S> {
+    stream: S,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl<S: Read> Read for Connection<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.stream.read(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+                    if self.shutdown.load(Ordering::SeqCst) {
+                        return Ok(0);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<S: Write> Write for Connection<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<'a> Server<'a> {
+    /// Answers requests on the transport until it closes or the server shuts down.
+    pub fn listen(&mut self, transport: &Transport) -> io::Result<()> {
+        info!("Listening on {}", transport);
+        match *transport {
+            Transport::Tcp { ref hostname, port } => {
+                let listener = TcpListener::bind(format!("{}:{}", hostname, port))?;
+                listener.set_nonblocking(true)?;
+                self.accept_connections(|| {
+                    let (stream, _) = listener.accept()?;
+                    stream.set_nonblocking(false)?;
+                    stream.set_read_timeout(Some(READ_POLL_INTERVAL))?;
+                    Ok(stream)
+                });
+                Ok(())
+            }
+            Transport::Unix { ref path, mode } => self.listen_unix(path, mode),
+            Transport::Stdio => self.handle_connection(StdioStream { stdin: io::stdin(), stdout: io::stdout() }, true),
+        }
+    }
+
+    #[cfg(unix)]
+    fn listen_unix(&mut self, path: &Path, mode: u32) -> io::Result<()> {
+        let listener = bind_unix_socket(path, mode)?;
+        listener.set_nonblocking(true)?;
+        self.accept_connections(|| {
+            let (stream, _) = listener.accept()?;
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(READ_POLL_INTERVAL))?;
+            Ok(stream)
+        });
+        drop(listener);
+        fs::remove_file(path)
+    }
+
+    #[cfg(not(unix))]
+    fn listen_unix(&mut self, _path: &Path, _mode: u32) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "Unix domain sockets are not supported on this platform"))
+    }
+
+    /// Answers each connection on a thread of its own until the server shuts down, with requests
+    /// executed one at a time. `accept` is nonblocking and the reads of the connections it
+    /// accepts time out, so a shutdown is noticed even when no client connects or sends a
+    /// request.
+    fn accept_connections<S: Read + Write + Send, F: FnMut() -> io::Result<S>>(&mut self, mut accept: F) {
+        let shutdown = self.shutdown.clone();
+        let server = Mutex::new(self);
+        thread::scope(|scope| {
+            while !lock(&server).is_shutting_down() {
+                match accept() {
+                    Ok(stream) => {
+                        let server = &server;
+                        let connection = Connection { stream, shutdown: shutdown.clone() };
+                        scope.spawn(move || {
+                            if let Err(e) = serve_connection(server, connection, false) {
+                                warn!("Connection failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::Interrupted => {
+                        thread::sleep(ACCEPT_POLL_INTERVAL);
+                    }
+                    Err(e) => warn!("Connection failed: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Answers the requests on the only connection of the server.
+    pub fn handle_connection<S: Read + Write>(&mut self, stream: S, keep_open: bool) -> io::Result<()> {
+        serve_connection(&Mutex::new(self), stream, keep_open)
+    }
+}
+
+fn lock<'m, 's, 'a>(server: &'m Mutex<&'s mut Server<'a>>) -> MutexGuard<'m, &'s mut Server<'a>> {
+    // requests which panic are answered with an error, so the server is still usable, except
+    // for those modifying tags, which shut the server down
+    server.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Answers the requests on a connection. A connection starting with a `Content-Length` header
+/// is answered in JSON-RPC until the client disconnects. Otherwise requests are text lines:
+/// just the first one, or every line with each reply on a line of its own when the connection
+/// is kept open. The server is locked only while it executes a request.
+fn serve_connection<S: Read + Write>(server: &Mutex<&mut Server>, stream: S, keep_open: bool) -> io::Result<()> {
+    let (shutdown, mut format) = {
+        let mut server = lock(server);
+        server.connection_count += 1;
+        (server.shutdown.clone(), server.default_format)
+    };
+
+    let mut reader = BufReader::new(stream);
+    if json_rpc::is_framed(reader.fill_buf()?) {
+        loop {
+            let message = match json_rpc::read_message(&mut reader) {
+                Ok(Some(v)) => v,
+                Ok(None) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                    warn!("Invalid message: {}", e);
+                    return json_rpc::write_message(reader.get_mut(), &json_rpc::invalid_message_response(e));
+                }
+                Err(e) => return Err(e),
+            };
+            let response = lock(server).handle_json_rpc(&message);
+            if let Some(response) = response {
+                json_rpc::write_message(reader.get_mut(), &response)?;
+            }
+            if shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    let mut line = Vec::new();
+    while reader.by_ref().take(MAX_REQUEST_LENGTH as u64 + 1).read_until(b'\n', &mut line)? > 0
+        && !shutdown.load(Ordering::SeqCst) {
+        let reply = match str::from_utf8(&line) {
+            _ if line.len() > MAX_REQUEST_LENGTH && !line.ends_with(b"\n") => {
+                warn!("Request longer than {} bytes", MAX_REQUEST_LENGTH);
+                skip_line(&mut reader)?;
+                let message = format!("Request is longer than {} bytes", MAX_REQUEST_LENGTH);
+                CommandError::Usage(message).to_reply().serialize(format)
+            }
+            Err(e) => {
+                warn!("Invalid request: {}", e);
+                CommandError::Parse(format!("Request is not valid UTF-8: {}", e)).to_reply().serialize(format)
+            }
+            Ok(request) => {
+                let request = request.trim_end_matches(['\r', '\n']);
+                if keep_open && request.is_empty() {
+                    line.clear();
+                    continue;
+                }
+                // the `format` request changes the format for the rest of this connection only
+                let mut server = lock(server);
+                server.format = format;
+                let reply = server.execute(request);
+                format = server.format;
+                reply
+            }
+        };
+        let stream = reader.get_mut();
+        stream.write_all(reply.as_bytes())?;
+        if !keep_open {
+            return stream.flush();
+        }
+        stream.write_all(b"\n")?;
+        stream.flush()?;
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+/// Discards the rest of the line being read, without holding on to it.
+fn skip_line<R: BufRead>(reader: &mut R) -> io::Result<()> {
+    loop {
+        let (done, length) = {
+            let buffer = reader.fill_buf()?;
+            match buffer.iter().position(|&v| v == b'\n') {
+                Some(v) => (true, v + 1),
+                None => (buffer.is_empty(), buffer.len()),
+            }
+        };
+        reader.consume(length);
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// Listens on the socket at `path`, which only the users `mode` allows may connect to. A socket
+/// left behind by a server which is no longer running is replaced.
+#[cfg(unix)]
+pub fn bind_unix_socket(path: &Path, mode: u32) -> io::Result<::std::os::unix::net::UnixListener> {
+    use std::os::unix::fs::DirBuilderExt;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+    use std::os::unix::net::UnixStream;
+
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("Another server is listening on {}", path.display())));
+        }
+        fs::remove_file(path)?;
+    }
+
+    // the socket is bound in a directory only this user may enter, and moved into place once
+    // it has its mode, so no other user can connect while it has the permissions of the umask
+    let file_name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file path", path.display())))?;
+    let staging_directory = path.with_file_name(format!(".{}.{}", file_name.to_string_lossy(), ::std::process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&staging_directory)?;
+    let staged_path = staging_directory.join("socket");
+    let result = UnixListener::bind(&staged_path).and_then(|listener| {
+        fs::set_permissions(&staged_path, fs::Permissions::from_mode(mode))?;
+        fs::rename(&staged_path, path)?;
+        Ok(listener)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&staged_path);
+    }
+    fs::remove_dir(&staging_directory)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Cursor;
+    use server::commands::Echo;
+    use server::yaml_rust::YamlLoader;
+
+    struct TestStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for TestStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for TestStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn replies(input: &str, keep_open: bool) -> String {
+        let mut server = Server::new();
+        server.add_command(Box::new(Echo::new()));
+
+        let mut stream = TestStream { input: Cursor::new(input.as_bytes().to_vec()), output: Vec::new() };
+        server.handle_connection(&mut stream, keep_open).unwrap();
+        String::from_utf8(stream.output).unwrap()
+    }
+
+    fn config(yaml: &str) -> Yaml {
+        YamlLoader::load_from_str(yaml).unwrap().remove(0)
+    }
+
+    #[test]
+    fn should_read_transport_from_config() {
+        assert_eq!(Transport::Tcp { hostname: "localhost".to_string(), port: 9092 }, transport_from_config(&config("port: 9092"), ".enigma"));
+        assert_eq!(Transport::Unix { path: PathBuf::from(".enigma/enigma.sock"), mode: DEFAULT_SOCKET_MODE },
+                   transport_from_config(&config("transport: unix"), ".enigma"));
+        assert_eq!(Transport::Unix { path: PathBuf::from("/tmp/enigma.sock"), mode: 0o660 },
+                   transport_from_config(&config("transport: unix\nsocket_path: /tmp/enigma.sock\nsocket_mode: 660"), ".enigma"));
+    }
+
+    #[test]
+    fn should_override_configured_transport() {
+        let configured = Transport::Tcp { hostname: "0.0.0.0".to_string(), port: 9092 };
+        assert_eq!(configured, TransportOverrides::default().apply(configured.clone()));
+        assert_eq!(Transport::Tcp { hostname: "0.0.0.0".to_string(), port: 9093 },
+                   TransportOverrides { port: Some(9093), ..Default::default() }.apply(configured.clone()));
+        assert_eq!(Transport::Tcp { hostname: "127.0.0.1".to_string(), port: 9092 },
+                   TransportOverrides { hostname: Some("127.0.0.1".to_string()), ..Default::default() }
+                       .apply(Transport::Unix { path: PathBuf::from("enigma.sock"), mode: DEFAULT_SOCKET_MODE }));
+        assert_eq!(Transport::Stdio, TransportOverrides { stdio: true, port: Some(9093), ..Default::default() }.apply(configured));
+    }
+
+    #[test]
+    fn should_answer_one_request_per_connection() {
+        assert_eq!("\"hello\"", replies("echo hello\r\necho world\n", false));
+    }
+
+    #[test]
+    fn should_answer_every_line_when_kept_open() {
+        assert_eq!("\"hello\"\n\"world\"\n", replies("echo hello\n\necho world", true));
+    }
+
+    #[test]
+    fn should_stop_reading_after_shutdown() {
+        assert_eq!("\"hello\"\nt\n", replies("echo hello\nshutdown\necho world\n", true));
+    }
+
+    #[test]
+    fn should_answer_failed_requests_with_errors() {
+        assert_eq!("(error :kind not-found :message \"Unrecognized command 'bogus'\")\n\"world\"\n", replies("bogus\necho world\n", true));
+
+        let mut server = Server::new();
+        server.add_command(Box::new(Echo::new()));
+        let mut stream = TestStream { input: Cursor::new(b"echo \xff\necho world\n".to_vec()), output: Vec::new() };
+        server.handle_connection(&mut stream, true).unwrap();
+        let output = String::from_utf8(stream.output).unwrap();
+        assert!(output.starts_with("(error :kind parse :message \"Request is not valid UTF-8"));
+        assert!(output.ends_with("\n\"world\"\n"));
+    }
+
+    #[test]
+    fn should_reject_too_large_messages() {
+        let output = replies(&format!("Content-Length: {}\r\n\r\n", json_rpc::MAX_CONTENT_LENGTH + 1), false);
+        assert!(output.contains("\"code\":-32600"));
+        assert!(output.contains("exceeds the limit"));
+    }
+
+    #[test]
+    fn should_reject_too_long_requests() {
+        let output = replies(&format!("echo {}\necho world\n", "a".repeat(MAX_REQUEST_LENGTH)), true);
+        assert_eq!(format!("(error :kind usage :message \"Request is longer than {} bytes\")\n\"world\"\n", MAX_REQUEST_LENGTH), output);
+        // the limit is on the request, without the newline
+        assert!(replies(&format!("echo {}\n", "a".repeat(MAX_REQUEST_LENGTH - 5)), false).starts_with("\"aaa"));
+    }
+
+    #[test]
+    fn should_answer_while_other_connections_are_idle() {
+        use std::net::TcpStream;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let server = thread::spawn(move || {
+            let mut server = Server::new();
+            server.add_command(Box::new(Echo::new()));
+            server.accept_connections(|| {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(READ_POLL_INTERVAL))?;
+                Ok(stream)
+            });
+        });
+
+        let request = |request: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            reply
+        };
+        let mut idle = TcpStream::connect(address).unwrap();
+        idle.write_all(b"Content-Length: 2\r\n\r\n[]").unwrap();
+        assert_eq!("\"hello\"", request("echo hello\n"));
+        assert_eq!("t", request("shutdown\n"));
+        // the idle connection does not keep the server from shutting down
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_restrict_and_replace_sockets() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join(format!("enigma-socket-test-{}", ::std::process::id()));
+        {
+            let _listener = bind_unix_socket(&path, 0o600).unwrap();
+            assert_eq!(0o600, fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+            assert_eq!(io::ErrorKind::AddrInUse, bind_unix_socket(&path, 0o600).unwrap_err().kind());
+            assert!(!path.with_file_name(format!(".enigma-socket-test-{0}.{0}", ::std::process::id())).exists());
+        }
+        // the socket of the dropped listener is left behind
+        let _listener = bind_unix_socket(&path, 0o660).unwrap();
+        assert_eq!(0o660, fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use tags::TagDatabase;
+use tags::TagKind;
+use tags::ambiguous_file_error;
+use tags::closest_files;
+use tags::directory_distance;
+
+/// The files directly connected to a file, and all files connected to it transitively.
+pub type DirectAndTransitive<'a> = (Vec<&'a str>, Vec<&'a str>);
+
+/// Which file includes which, built from the include tags ctags emits with `--extras=+r` and
+/// resolved against the `F` file tags.
+pub struct IncludeGraph {
+    files: Vec<String>,
+    includes: Vec<Vec<usize>>,
+    included_by: Vec<Vec<usize>>,
+    /// Per file, its include directives which match several files equally well.
+    ambiguous_includes: Vec<Vec<String>>,
+}
+
+impl IncludeGraph {
+    pub fn new() -> IncludeGraph {
+        IncludeGraph {
+            files: Vec::new(),
+            includes: Vec::new(),
+            included_by: Vec::new(),
+            ambiguous_includes: Vec::new(),
+        }
+    }
+
+    pub fn build(tag_database: &TagDatabase) -> IncludeGraph {
+        let mut graph = IncludeGraph::new();
+
+        // files by their file name, to resolve include directives relative to any include root
+        let mut files_by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+        let mut file_indices: HashMap<&str, usize> = HashMap::new();
+        for tag in tag_database.all_tags().filter(|v| v.kind() == TagKind::File) {
+            if file_indices.contains_key(tag.source_file()) {
+                continue;
+            }
+            let index = graph.add_file(tag.source_file());
+            file_indices.insert(tag.source_file(), index);
+            files_by_name.entry(file_name(tag.source_file())).or_default().push(index);
+        }
+
+        for tag in tag_database.all_tags().filter(|v| v.kind() == TagKind::HeaderInclude) {
+            let includer = match file_indices.get(tag.source_file()) {
+                Some(&v) => v,
+                None => {
+                    let index = graph.add_file(tag.source_file());
+                    file_indices.insert(tag.source_file(), index);
+                    index
+                }
+            };
+
+            let include_name = tag.name().replace('\\', "/");
+            match graph.resolve_include(includer, &include_name, &files_by_name, &file_indices) {
+                Ok(Some(included)) => {
+                    if !graph.includes[includer].contains(&included) {
+                        graph.includes[includer].push(included);
+                        graph.included_by[included].push(includer);
+                    }
+                }
+                Ok(None) => {}
+                Err(message) => graph.ambiguous_includes[includer].push(message),
+            }
+        }
+
+        graph
+    }
+
+    /// The files `file_path` includes directly, and all files it includes transitively.
+    pub fn includes(&self, file_path: &str) -> Result<Option<DirectAndTransitive<'_>>, String> {
+        Ok(self.find_file(file_path)?.map(|v| self.direct_and_transitive(v, &self.includes)))
+    }
+
+    /// The files including `file_path` directly, and all files including it transitively.
+    pub fn included_by(&self, file_path: &str) -> Result<Option<DirectAndTransitive<'_>>, String> {
+        Ok(self.find_file(file_path)?.map(|v| self.direct_and_transitive(v, &self.included_by)))
+    }
+
+    /// Why the include directives of `file_path` and of the files it includes which match several
+    /// files were left unresolved.
+    pub fn ambiguous_includes(&self, file_path: &str) -> Result<Vec<&str>, String> {
+        let file = match self.find_file(file_path)? {
+            None => return Ok(Vec::new()),
+            Some(v) => v,
+        };
+        let (_, transitive) = self.direct_and_transitive(file, &self.includes);
+        let mut messages: Vec<&str> = self.ambiguous_includes[file].iter().map(|v| &v[..]).collect();
+        for included in transitive {
+            let index = self.files.iter().position(|v| v == included).unwrap();
+            messages.extend(self.ambiguous_includes[index].iter().map(|v| &v[..]));
+        }
+        Ok(messages)
+    }
+
+    /// The shortest chain of includes leading from `from_file_path` to `to_file_path`, both ends included.
+    pub fn include_path(&self, from_file_path: &str, to_file_path: &str) -> Result<Option<Vec<&str>>, String> {
+        let from = match self.find_file(from_file_path)? {
+            None => return Ok(None),
+            Some(v) => v,
+        };
+        let to = match self.find_file(to_file_path)? {
+            None => return Ok(None),
+            Some(v) => v,
+        };
+
+        let mut previous: Vec<Option<usize>> = vec![None; self.files.len()];
+        let mut visited = vec![false; self.files.len()];
+        let mut queue = VecDeque::new();
+        visited[from] = true;
+        queue.push_back(from);
+
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut path = vec![&self.files[to][..]];
+                let mut node = to;
+                while let Some(v) = previous[node] {
+                    path.push(&self.files[v]);
+                    node = v;
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+
+            for &next in &self.includes[current] {
+                if !visited[next] {
+                    visited[next] = true;
+                    previous[next] = Some(current);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The file an include directive of `includer` names. A path relative to the directory of the
+    /// includer wins, then the files sharing the most trailing path components with the include,
+    /// then the one closest to the includer.
+    fn resolve_include(&self, includer: usize, include_name: &str, files_by_name: &HashMap<&str, Vec<usize>>,
+                       file_indices: &HashMap<&str, usize>) -> Result<Option<usize>, String> {
+        let includer_path = &self.files[includer];
+        let directory = match includer_path.rfind('/') {
+            None => "",
+            Some(v) => &includer_path[..v + 1],
+        };
+        if let Some(&included) = file_indices.get(&normalize_path(&format!("{}{}", directory, include_name))[..]) {
+            return Ok(Some(included));
+        }
+
+        // leading `..` components can't be matched against the end of a path
+        let include_name = normalize_path(include_name);
+        let mut include_suffix = &include_name[..];
+        while let Some(rest) = include_suffix.strip_prefix("../") {
+            include_suffix = rest;
+        }
+        let candidates = match files_by_name.get(file_name(include_suffix)) {
+            None => return Ok(None),
+            Some(v) => v,
+        };
+        let best_files = closest_files(candidates.iter().map(|&v| &self.files[v][..]), include_suffix);
+        let closest_distance = best_files.iter().map(|v| directory_distance(includer_path, v)).min();
+        let closest_files: Vec<&str> = best_files.iter().cloned()
+            .filter(|v| Some(directory_distance(includer_path, v)) == closest_distance)
+            .collect();
+        match closest_files.len() {
+            0 => Ok(None),
+            1 => Ok(Some(file_indices[closest_files[0]])),
+            _ => Err(ambiguous_file_error(&include_name, &closest_files)),
+        }
+    }
+
+    fn add_file(&mut self, file_path: &str) -> usize {
+        self.files.push(file_path.to_string());
+        self.includes.push(Vec::new());
+        self.included_by.push(Vec::new());
+        self.ambiguous_includes.push(Vec::new());
+        self.files.len() - 1
+    }
+
+    fn find_file(&self, file_path: &str) -> Result<Option<usize>, String> {
+        let best_files = closest_files(self.files.iter().map(|v| &v[..]), file_path);
+        match best_files.len() {
+            0 => Ok(None),
+            1 => Ok(self.files.iter().position(|v| v == best_files[0])),
+            _ => Err(ambiguous_file_error(file_path, &best_files)),
+        }
+    }
+
+    fn direct_and_transitive(&self, file: usize, edges: &[Vec<usize>]) -> DirectAndTransitive<'_> {
+        let direct = edges[file].iter().map(|&v| &self.files[v][..]).collect();
+
+        let mut visited = vec![false; self.files.len()];
+        let mut transitive = Vec::new();
+        let mut queue: VecDeque<usize> = edges[file].iter().cloned().collect();
+        visited[file] = true;
+        for &v in &edges[file] {
+            visited[v] = true;
+        }
+        while let Some(current) = queue.pop_front() {
+            transitive.push(&self.files[current][..]);
+            for &next in &edges[current] {
+                if !visited[next] {
+                    visited[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        (direct, transitive)
+    }
+}
+
+fn file_name(file_path: &str) -> &str {
+    match file_path.rfind(['/', '\\']) {
+        None => file_path,
+        Some(v) => &file_path[v + 1..],
+    }
+}
+
+/// `file_path` with its `.` components dropped and its `..` components applied, except those
+/// leading out of the path.
+fn normalize_path(file_path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in file_path.split('/') {
+        match component {
+            "." => {}
+            ".." if components.last().is_some_and(|&v| v != ".." && !v.is_empty()) => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    components.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tags::TagDefinition;
+    use tags::TagFile;
+
+    fn test_database() -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in &[
+            "Main.cpp\tGame/Private/Main.cpp\t1;\"\tF\tline:1",
+            "Actor.h\tEngine/Public/GameFramework/Actor.h\t1;\"\tF\tline:1",
+            "Object.h\tEngine/Public/Object.h\t1;\"\tF\tline:1",
+            "Object.h\tOther/Object.h\t1;\"\tF\tline:1",
+            "GameFramework/Actor.h\tGame/Private/Main.cpp\t/^#include \"GameFramework\\/Actor.h\"$/;\"\th\tline:1\troles:local",
+            "Object.h\tEngine/Public/GameFramework/Actor.h\t/^#include \"Object.h\"$/;\"\th\tline:3\troles:local",
+            "vector\tEngine/Public/Object.h\t/^#include <vector>$/;\"\th\tline:2\troles:system",
+        ] {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_find_direct_and_transitive_includes() {
+        let graph = IncludeGraph::build(&test_database());
+        let (direct, transitive) = graph.includes("Game/Private/Main.cpp").unwrap().unwrap();
+        assert_eq!(vec!["Engine/Public/GameFramework/Actor.h"], direct);
+        assert_eq!(vec!["Engine/Public/GameFramework/Actor.h", "Engine/Public/Object.h"], transitive);
+    }
+
+    #[test]
+    fn should_find_reverse_dependencies() {
+        let graph = IncludeGraph::build(&test_database());
+        let (direct, transitive) = graph.included_by("/work/Engine/Public/Object.h").unwrap().unwrap();
+        assert_eq!(vec!["Engine/Public/GameFramework/Actor.h"], direct);
+        assert_eq!(vec!["Engine/Public/GameFramework/Actor.h", "Game/Private/Main.cpp"], transitive);
+    }
+
+    #[test]
+    fn should_find_shortest_include_path() {
+        let graph = IncludeGraph::build(&test_database());
+        assert_eq!(Ok(Some(vec!["Game/Private/Main.cpp", "Engine/Public/GameFramework/Actor.h", "Engine/Public/Object.h"])),
+                   graph.include_path("Game/Private/Main.cpp", "Engine/Public/Object.h"));
+        assert_eq!(Ok(None), graph.include_path("Engine/Public/Object.h", "Game/Private/Main.cpp"));
+    }
+
+    fn database_with(lines: &[&str]) -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in lines {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    #[test]
+    fn should_resolve_includes_relative_to_the_includer() {
+        let graph = IncludeGraph::build(&database_with(&[
+            "Main.cpp\tGame/Private/Main.cpp\t1;\"\tF\tline:1",
+            "Types.h\tGame/Types.h\t1;\"\tF\tline:1",
+            "Types.h\tGame/Private/Types.h\t1;\"\tF\tline:1",
+            "Types.h\tEngine/Types.h\t1;\"\tF\tline:1",
+            "../Types.h\tGame/Private/Main.cpp\t/^#include \"..\\/Types.h\"$/;\"\th\tline:1\troles:local",
+        ]));
+        let (direct, _) = graph.includes("Game/Private/Main.cpp").unwrap().unwrap();
+        assert_eq!(vec!["Game/Types.h"], direct);
+    }
+
+    #[test]
+    fn should_prefer_the_longest_matching_suffix() {
+        let graph = IncludeGraph::build(&database_with(&[
+            "Main.cpp\tGame/Main.cpp\t1;\"\tF\tline:1",
+            "Object.h\tGame/Object.h\t1;\"\tF\tline:1",
+            "Object.h\tEngine/UObject/Object.h\t1;\"\tF\tline:1",
+            "UObject/Object.h\tGame/Main.cpp\t/^#include \"UObject\\/Object.h\"$/;\"\th\tline:1\troles:local",
+        ]));
+        let (direct, _) = graph.includes("Game/Main.cpp").unwrap().unwrap();
+        assert_eq!(vec!["Engine/UObject/Object.h"], direct);
+    }
+
+    #[test]
+    fn should_report_ambiguous_includes() {
+        let graph = IncludeGraph::build(&database_with(&[
+            "Main.cpp\tGame/Main.cpp\t1;\"\tF\tline:1",
+            "Object.h\tEngine/Object.h\t1;\"\tF\tline:1",
+            "Object.h\tOther/Object.h\t1;\"\tF\tline:1",
+            "Object.h\tGame/Main.cpp\t/^#include \"Object.h\"$/;\"\th\tline:1\troles:local",
+        ]));
+        let (direct, _) = graph.includes("Game/Main.cpp").unwrap().unwrap();
+        assert!(direct.is_empty());
+        assert_eq!(Ok(vec!["'Object.h' is ambiguous, it matches Engine/Object.h, Other/Object.h"]),
+                   graph.ambiguous_includes("Game/Main.cpp"));
+        assert_eq!(Err("'Object.h' is ambiguous, it matches Engine/Object.h, Other/Object.h".to_string()),
+                   graph.included_by("Object.h"));
+    }
+}
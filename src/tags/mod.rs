@@ -1,5 +1,18 @@
 pub mod tag_definition;
 pub mod tag_file;
+pub mod include_graph;
+pub mod name_index;
+pub mod trigram_index;
+pub mod visit_history;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use self::include_graph::IncludeGraph;
+use self::tag_definition::same_file;
+use self::name_index::NameIndex;
+use self::trigram_index::TrigramIndex;
+use self::visit_history::VisitHistory;
 
 #[allow(dead_code)]
 pub type TagDefinition = self::tag_definition::TagDefinition;
@@ -84,18 +97,654 @@ pub fn tag_kind_from_str(tag_kind: &str) -> TagKind {
     }
 }
 
+/// The name of the kind as accepted by `tag_kind_from_str`.
+pub fn tag_kind_name(tag_kind: &TagKind) -> &'static str {
+    match *tag_kind {
+        TagKind::MacroDefinitions => "macro",
+        TagKind::EnumValue => "enum_value",
+        TagKind::FunctionDefinition => "function",
+        TagKind::Enum => "enum",
+        TagKind::HeaderInclude => "header_include",
+        TagKind::LocalVariable => "local_variable",
+        TagKind::ClassMember => "member",
+        TagKind::FunctionPrototype => "function_prototype",
+        TagKind::Struct => "struct",
+        TagKind::Typedef => "typedef",
+        TagKind::Union => "union",
+        TagKind::Variable => "variable",
+        TagKind::ForwardDeclaration => "forward_declaration",
+        TagKind::FunctionParameter => "function_parameter",
+        TagKind::GotoLabel => "goto_label",
+        TagKind::Class => "class",
+        TagKind::Namespace => "namespace",
+        TagKind::NamespaceAlias => "namespace_alias",
+        TagKind::NamespaceUsingStatement => "namespace_using_statement",
+        TagKind::File => "file",
+        TagKind::Unknown => "unknown",
+    }
+}
+
 pub struct TagDatabase {
-    pub tag_files: Vec<TagFile>
+    pub tag_files: Vec<TagFile>,
+    name_index: NameIndex,
+    name_trigrams: TrigramIndex,
+    declaration_trigrams: Option<TrigramIndex>,
+    tag_file_offsets: Vec<usize>,
+    tag_count: usize,
+    visit_history: VisitHistory,
+    /// The ids of the classes and structs with each name, unqualified.
+    classes_by_name: HashMap<String, Vec<u32>>,
+    /// The ids of the classes and structs deriving from a base with each name, unqualified.
+    subclasses_by_base: HashMap<String, Vec<u32>>,
+    include_graph: IncludeGraph,
 }
 
 impl TagDatabase {
     pub fn new() -> TagDatabase {
         TagDatabase {
             tag_files: Vec::new(),
+            name_index: NameIndex::new(),
+            name_trigrams: TrigramIndex::new(),
+            declaration_trigrams: None,
+            tag_file_offsets: Vec::new(),
+            tag_count: 0,
+            visit_history: VisitHistory::new(),
+            classes_by_name: HashMap::new(),
+            subclasses_by_base: HashMap::new(),
+            include_graph: IncludeGraph::new(),
+        }
+    }
+
+    /// Replaces the record of visited tags, e.g. with one loaded from the data directory. It is
+    /// kept when tags files are added, as visits are keyed by file, name and kind.
+    pub fn set_visit_history(&mut self, visit_history: VisitHistory) {
+        self.visit_history = visit_history;
+    }
+
+    /// Records a jump of `client` to `line` of `file_path`, to the tag on that line or else the
+    /// scope around it. False when there is no such tag.
+    pub fn record_visit(&mut self, client: &str, file_path: &str, line: usize) -> Result<bool, String> {
+        let tag_id = match self.tag_at(file_path, line)?.and_then(|v| v.id()) {
+            None => return Ok(false),
+            Some(v) => v,
+        };
+        let file_index = match self.tag_file_index(tag_id) {
+            None => return Ok(false),
+            Some(v) => v,
+        };
+        let tag = &self.tag_files[file_index].tags[tag_id as usize - self.tag_file_offsets[file_index]];
+        self.visit_history.record(client, tag);
+        Ok(true)
+    }
+
+    /// How much to boost the match score of `tag` for having been visited often and recently
+    /// by `client`.
+    pub fn frecency(&self, client: &str, tag: &TagDefinition) -> usize {
+        self.visit_history.frecency(client, tag)
+    }
+
+    /// Whether tags files added from now on also get their declaration text indexed for
+    /// substring searches. Declarations are much longer than names, so this is off by default.
+    pub fn set_index_declarations(&mut self, index_declarations: bool) {
+        if index_declarations && self.declaration_trigrams.is_none() {
+            self.declaration_trigrams = Some(TrigramIndex::new());
+        } else if !index_declarations {
+            self.declaration_trigrams = None;
+        }
+    }
+
+    /// Adds the tags file and indexes its names, and its declarations if enabled. Tags are
+    /// numbered in the order they were added, across every tags file. The include graph is
+    /// rebuilt, as includes in one tags file may resolve to files listed in another.
+    pub fn add_tag_file(&mut self, mut tag_file: TagFile) {
+        let offset = self.tag_count;
+        for (i, tag) in tag_file.tags.iter_mut().enumerate() {
+            let tag_id = (offset + i) as u32;
+            tag.set_id(tag_id);
+            self.name_index.add(tag.name());
+            self.name_trigrams.add(tag_id, tag.name());
+            if let Some(ref mut declaration_trigrams) = self.declaration_trigrams {
+                declaration_trigrams.add(tag_id, tag.declaration());
+            }
+            if tag.is_class() {
+                self.classes_by_name.entry(tag.name().to_string()).or_default().push(tag_id);
+                for base in tag.inherits() {
+                    self.subclasses_by_base.entry(simple_class_name(base).to_string()).or_default().push(tag_id);
+                }
+            }
+        }
+        self.tag_file_offsets.push(offset);
+        self.tag_count += tag_file.tags.len();
+        self.tag_files.push(tag_file);
+        self.include_graph = IncludeGraph::build(self);
+    }
+
+    /// Which file includes which, across every tags file added.
+    pub fn include_graph(&self) -> &IncludeGraph {
+        &self.include_graph
+    }
+
+    /// The tag numbered `tag_id` by `add_tag_file`.
+    pub fn tag(&self, tag_id: u32) -> Option<&TagDefinition> {
+        let file_index = self.tag_file_index(tag_id)?;
+        self.tag_files[file_index].tags.get(tag_id as usize - self.tag_file_offsets[file_index])
+    }
+
+    /// The index of the tags file holding the tag numbered `tag_id`.
+    fn tag_file_index(&self, tag_id: u32) -> Option<usize> {
+        match self.tag_file_offsets.binary_search(&(tag_id as usize)) {
+            Ok(v) => Some(v),
+            Err(0) => None,
+            Err(v) => Some(v - 1),
+        }
+    }
+
+    /// The tags whose name, or declaration when `in_declarations` is set, may contain every one
+    /// of `literals`, in the order they were added. The candidates still need to be verified.
+    /// Every tag is a candidate when the literals are too short to use the trigram index, or
+    /// when declarations were not indexed.
+    pub fn substring_candidates<'a>(&'a self, literals: &[String], in_declarations: bool)
+                                    -> Box<dyn Iterator<Item = &'a TagDefinition> + 'a> {
+        let index = if in_declarations { self.declaration_trigrams.as_ref() } else { Some(&self.name_trigrams) };
+        match index.and_then(|v| v.candidates(literals)) {
+            None => self.all_tags(),
+            Some(tag_ids) => Box::new(tag_ids.into_iter().filter_map(move |v| self.tag(v))),
+        }
+    }
+
+    /// Whether any tag, definition or reference, is named exactly `name`.
+    pub fn has_name(&self, name: &str) -> bool {
+        self.name_index.contains(name)
+    }
+
+    /// Up to `limit` names within a couple of typos of `name`, for replies that found nothing.
+    pub fn suggest_names(&self, name: &str, limit: usize) -> Vec<&str> {
+        self.name_index.suggest(name, limit)
+    }
+
+    pub fn all_tags<'a>(&'a self) -> Box<dyn Iterator<Item = &'a TagDefinition> + 'a> {
+        Box::new(self.tag_files.iter().flat_map(|v| &v.tags))
+    }
+
+    /// Every tag except the reference tags, which record occurrences rather than definitions.
+    pub fn all_definitions<'a>(&'a self) -> Box<dyn Iterator<Item = &'a TagDefinition> + 'a> {
+        Box::new(self.all_tags().filter(|v| !v.is_reference()))
+    }
+
+    /// The reference tags named `name`, grouped by the file they occur in.
+    pub fn references_to(&self, name: &str) -> Vec<(&str, Vec<&TagDefinition>)> {
+        let mut references: Vec<(&str, Vec<&TagDefinition>)> = Vec::new();
+        for tag in self.all_tags().filter(|v| v.is_reference() && v.name() == name) {
+            match references.iter_mut().find(|v| v.0 == tag.source_file()) {
+                Some(v) => v.1.push(tag),
+                None => references.push((tag.source_file(), vec![tag])),
+            }
+        }
+        references
+    }
+
+    /// How strongly tags are preferred over same-named tags, higher is better: the priority of
+    /// their tags file first, then how close their directory is to that of `from_file`. The file
+    /// is resolved once, for ranking any number of tags.
+    pub fn preference<'a>(&'a self, from_file: Option<&'a str>) -> impl Fn(&TagDefinition) -> i64 + 'a {
+        let from_file = from_file.map(|v| self.resolve_file(v).unwrap_or(v));
+        move |tag| {
+            let priority = tag.id()
+                .and_then(|v| self.tag_file_index(v))
+                .map(|v| self.tag_files[v].priority)
+                .unwrap_or(0);
+            let distance = match from_file {
+                None => 0,
+                Some(from_file) => directory_distance(from_file, tag.source_file()).min(MAX_DIRECTORY_DISTANCE),
+            };
+            priority as i64 * (MAX_DIRECTORY_DISTANCE as i64 + 1) - distance as i64
+        }
+    }
+
+    /// The path of `file_path` as stored in the tags files, looked up through the `F` file tags.
+    /// Of several matching files, the one sharing the most trailing path components with
+    /// `file_path` is taken, and an error names them when that still leaves more than one.
+    pub fn resolve_file<'a>(&'a self, file_path: &'a str) -> Result<&'a str, String> {
+        let files = self.all_tags().filter(|v| v.kind() == TagKind::File).map(|v| v.source_file());
+        let best_files = closest_files(files, file_path);
+        match best_files.len() {
+            0 => Ok(file_path),
+            1 => Ok(best_files[0]),
+            _ => Err(ambiguous_file_error(file_path, &best_files)),
         }
     }
 
-    pub fn all_tags<'a>(&'a self) -> Box<Iterator<Item = &'a TagDefinition> + 'a> {
-        Box::new(self.tag_files.iter().map(|v| &v.tags).flat_map(|v| v))
+    /// Every tag located in `file_path` except the file tag itself, in line order.
+    pub fn tags_in_file(&self, file_path: &str) -> Result<Vec<&TagDefinition>, String> {
+        let source_file = self.resolve_file(file_path)?;
+        let mut tags: Vec<&TagDefinition> = self.all_tags()
+            .filter(|v| v.kind() != TagKind::File && v.source_file() == source_file)
+            .collect();
+        tags.sort_by_key(|v| v.source_line());
+        Ok(tags)
+    }
+
+    /// The innermost function, class or namespace in `file_path` whose range contains `line`,
+    /// along with that range. Tags without an `end:` field extend up to their next sibling.
+    pub fn scope_at(&self, file_path: &str, line: usize) -> Result<Option<(&TagDefinition, usize, usize)>, String> {
+        let tags = self.tags_in_file(file_path)?;
+        let parents = scope_parents(&tags);
+
+        let mut ends: Vec<usize> = Vec::with_capacity(tags.len());
+        for (i, tag) in tags.iter().enumerate() {
+            let end = match tag.end_line() {
+                Some(v) => v,
+                None => {
+                    let next_sibling = (i + 1..tags.len())
+                        .find(|&j| parents[j] == parents[i] && tags[j].source_line() > tag.source_line());
+                    match (next_sibling, parents[i]) {
+                        (Some(j), _) => tags[j].source_line() - 1,
+                        (None, Some(parent)) => ends[parent],
+                        (None, None) => usize::MAX,
+                    }
+                }
+            };
+            ends.push(end);
+        }
+
+        let mut innermost: Option<(&TagDefinition, usize, usize)> = None;
+        for (i, tag) in tags.iter().enumerate() {
+            if !(tag.is_container() || tag.is_method()) || tag.source_line() > line || ends[i] < line {
+                continue;
+            }
+            let is_inner = match innermost {
+                None => true,
+                Some((_, start, end)) => tag.source_line() >= start && ends[i] <= end,
+            };
+            if is_inner {
+                innermost = Some((tag, tag.source_line(), ends[i]));
+            }
+        }
+        Ok(innermost)
+    }
+
+    /// The definition on `line` of `file_path`, or the innermost scope containing the line.
+    pub fn tag_at(&self, file_path: &str, line: usize) -> Result<Option<&TagDefinition>, String> {
+        let tag = self.tags_in_file(file_path)?.into_iter()
+            .find(|v| v.source_line() == line && !v.is_reference());
+        match tag {
+            Some(tag) => Ok(Some(tag)),
+            None => self.scope_at(file_path, line).map(|v| v.map(|v| v.0)),
+        }
+    }
+
+    /// Classes and structs deriving from `class_name`, directly or transitively, nearest first.
+    pub fn subclasses_of(&self, class_name: &str) -> Vec<&TagDefinition> {
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut result: Vec<&TagDefinition> = Vec::new();
+        let mut frontier: Vec<&TagDefinition> = self.classes_named(class_name);
+
+        while !frontier.is_empty() {
+            let mut next = Vec::new();
+            for base in &frontier {
+                let base_name = base.qualified_name();
+                let candidates = self.subclasses_by_base.get(base.name()).into_iter().flatten().filter_map(|&v| self.tag(v));
+                for class in candidates {
+                    let derives_from_base = class.inherits().iter()
+                        .filter(|v| simple_class_name(v) == base.name())
+                        .any(|v| self.resolve_base(class, v).iter().any(|v| v.qualified_name() == base_name));
+                    if derives_from_base && visited.insert(class.qualified_name()) {
+                        next.push(class);
+                    }
+                }
+            }
+            result.extend(next.iter());
+            frontier = next;
+        }
+
+        result
+    }
+
+    /// Classes and structs `class_name` derives from, directly or transitively, nearest first.
+    pub fn base_classes_of(&self, class_name: &str) -> Vec<&TagDefinition> {
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut result: Vec<&TagDefinition> = Vec::new();
+        let mut frontier: Vec<&TagDefinition> = self.classes_named(class_name);
+
+        while !frontier.is_empty() {
+            let mut next = Vec::new();
+            for class in &frontier {
+                for base in class.inherits().iter().flat_map(|v| self.resolve_base(class, v)) {
+                    if visited.insert(base.qualified_name()) {
+                        next.push(base);
+                    }
+                }
+            }
+            result.extend(next.iter());
+            frontier = next;
+        }
+
+        result
+    }
+
+    /// Methods named `method_name` declared or defined directly inside `class`.
+    pub fn methods_of<'a>(&'a self, class: &TagDefinition, method_name: &str) -> Vec<&'a TagDefinition> {
+        let class_name = class.qualified_name();
+        self.all_tags()
+            .filter(|v| v.is_method() && v.name() == method_name && v.scope_name() == Some(&class_name[..]))
+            .collect()
+    }
+
+    /// Same-named methods in every transitive subclass of the method's class.
+    pub fn find_overrides(&self, class_name: &str, method_name: &str) -> Vec<&TagDefinition> {
+        self.subclasses_of(class_name).iter()
+            .flat_map(|v| self.methods_of(v, method_name))
+            .collect()
+    }
+
+    /// Same-named methods in the base classes of the method's class, nearest base first.
+    pub fn find_overridden(&self, class_name: &str, method_name: &str) -> Vec<&TagDefinition> {
+        self.base_classes_of(class_name).iter()
+            .flat_map(|v| self.methods_of(v, method_name))
+            .collect()
+    }
+
+    /// The classes and structs a name given in a request refers to, in any scope unless it is
+    /// qualified.
+    fn classes_named(&self, class_name: &str) -> Vec<&TagDefinition> {
+        let mut visited: HashSet<String> = HashSet::new();
+        self.classes_by_name.get(simple_class_name(class_name)).into_iter().flatten()
+            .filter_map(|&v| self.tag(v))
+            .filter(|v| names_class(v, class_name) && visited.insert(v.qualified_name()))
+            .collect()
+    }
+
+    /// The classes `base`, as written in the `inherits:` field of `class`, refers to. Like the
+    /// compiler, the scope `class` is declared in is searched first, then the scopes enclosing
+    /// it, up to the global scope.
+    fn resolve_base(&self, class: &TagDefinition, base: &str) -> Vec<&TagDefinition> {
+        let base = strip_template_arguments(base);
+        let classes: Vec<&TagDefinition> = self.classes_by_name.get(simple_class_name(base)).into_iter().flatten()
+            .filter_map(|&v| self.tag(v))
+            .collect();
+        if let Some(global_name) = base.strip_prefix("::") {
+            return classes.into_iter().filter(|v| v.qualified_name() == global_name).collect();
+        }
+
+        let mut scope = class.scope_name();
+        loop {
+            let qualified_name = match scope {
+                None => base.to_string(),
+                Some(scope) => format!("{}::{}", scope, base),
+            };
+            let matches: Vec<&TagDefinition> = classes.iter().cloned().filter(|v| v.qualified_name() == qualified_name).collect();
+            if !matches.is_empty() {
+                return matches;
+            }
+            scope = match scope {
+                None => return Vec::new(),
+                Some(scope) => scope.rfind("::").map(|v| &scope[..v]),
+            };
+        }
+    }
+}
+
+/// For every tag of a file, in line order, the index of the container tag it is scoped in.
+/// Nested scopes are qualified (`Outer::Inner`), so the parent is the closest container above
+/// the tag whose qualified name equals the tag's scope.
+pub fn scope_parents(tags: &[&TagDefinition]) -> Vec<Option<usize>> {
+    tags.iter().enumerate().map(|(i, tag)| {
+        let scope_name = tag.scope_name()?;
+        (0..i).rev().find(|&j| tags[j].is_container() && tags[j].qualified_name() == scope_name)
+    }).collect()
+}
+
+/// Directory distances are capped so that they never outweigh a difference in priority.
+const MAX_DIRECTORY_DISTANCE: usize = 1000;
+
+/// Of the `files` which `file_path` names, the ones sharing the most trailing path components
+/// with it. An exact match wins over a longer path ending in the same components.
+pub fn closest_files<'a, I: IntoIterator<Item = &'a str>>(files: I, file_path: &str) -> Vec<&'a str> {
+    let mut best_files: Vec<&str> = Vec::new();
+    let mut best_match = (0, false);
+    for file in files.into_iter().filter(|v| same_file(v, file_path)) {
+        let path_match = (common_suffix_length(file, file_path), file == file_path);
+        if path_match > best_match {
+            best_files.clear();
+            best_match = path_match;
+        }
+        if path_match == best_match && !best_files.contains(&file) {
+            best_files.push(file);
+        }
+    }
+    best_files
+}
+
+fn ambiguous_file_error(file_path: &str, files: &[&str]) -> String {
+    format!("'{}' is ambiguous, it matches {}", file_path, files.join(", "))
+}
+
+/// The number of directories to go up from the directory of `from` and back down to reach the
+/// directory of `to`. Paths are compared case-insensitively, with either kind of slash.
+fn directory_distance(from: &str, to: &str) -> usize {
+    let directories = |path: &str| -> Vec<String> {
+        let path = path.replace('\\', "/").to_lowercase();
+        let mut components: Vec<String> = path.split('/').filter(|v| !v.is_empty()).map(|v| v.to_string()).collect();
+        components.pop();
+        components
+    };
+    let from = directories(from);
+    let to = directories(to);
+    let common = from.iter().zip(to.iter()).take_while(|v| v.0 == v.1).count();
+    (from.len() - common) + (to.len() - common)
+}
+
+/// Whether `name`, as given in a request, refers to `class`. Template arguments are ignored and
+/// unqualified names match classes in any scope.
+fn names_class(class: &TagDefinition, name: &str) -> bool {
+    let name = strip_template_arguments(name);
+    let qualified_name = class.qualified_name();
+    qualified_name == name || class.name() == name || qualified_name.ends_with(&format!("::{}", name))
+}
+
+/// How many path components at the end of `left` and `right` are the same.
+fn common_suffix_length(left: &str, right: &str) -> usize {
+    let left = left.replace('\\', "/");
+    let right = right.replace('\\', "/");
+    left.rsplit('/').zip(right.rsplit('/')).take_while(|&(left, right)| left == right).count()
+}
+
+fn strip_template_arguments(name: &str) -> &str {
+    match name.find('<') {
+        None => name,
+        Some(v) => &name[..v],
+    }
+}
+
+/// The name of a class without its scope or template arguments, e.g. `Base` for
+/// `Game::Base<int>`.
+fn simple_class_name(name: &str) -> &str {
+    let name = strip_template_arguments(name);
+    match name.rfind("::") {
+        None => name,
+        Some(v) => &name[v + 2..],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indexed_database(lines: &[&str]) -> TagDatabase {
+        let mut tag_database = TagDatabase::new();
+        tag_database.set_index_declarations(true);
+        for chunk in lines.chunks(2) {
+            let mut tag_file = TagFile::new();
+            for line in chunk {
+                tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+            }
+            tag_database.add_tag_file(tag_file);
+        }
+        tag_database
+    }
+
+    fn database(lines: &[&str]) -> TagDatabase {
+        let mut tag_file = TagFile::new();
+        for line in lines {
+            tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+        }
+        let mut tag_database = TagDatabase::new();
+        tag_database.add_tag_file(tag_file);
+        tag_database
+    }
+
+    fn test_database() -> TagDatabase {
+        database(&[
+            "Test\tTest.h\t/^class Test {$/;\"\tc\tline:14",
+            "PureVirtual\tTest.h\t/^\tvirtual void PureVirtual() = 0;$/;\"\tp\tline:17\tclass:Test\ttyperef:typename:void",
+            "SubTest\tTest.h\t/^class SubTest : public Test {$/;\"\tc\tline:20\tinherits:Test",
+            "PureVirtual\tTest.h\t/^    virtual void PureVirtual() {$/;\"\tf\tline:22\tclass:SubTest\ttyperef:typename:void",
+            "SubSubTest\tTest.h\t/^class SubSubTest : public SubTest {$/;\"\tc\tline:26\tinherits:SubTest",
+            "PureVirtual\tTest.h\t/^    void PureVirtual() override;$/;\"\tp\tline:28\tclass:SubSubTest\ttyperef:typename:void",
+        ])
+    }
+
+    fn scope_database() -> TagDatabase {
+        database(&[
+            "Game\tGame.h\t/^namespace Game {$/;\"\tn\tline:1\tend:30",
+            "Test\tGame.h\t/^class Test {$/;\"\tc\tline:3\tnamespace:Game\tend:12",
+            "DoTest\tGame.h\t/^\tint DoTest() const {$/;\"\tf\tline:5\tclass:Game::Test",
+            "Count\tGame.h\t/^\tint Count;$/;\"\tm\tline:9\tclass:Game::Test",
+            "Helper\tGame.h\t/^void Helper() {$/;\"\tf\tline:14\tnamespace:Game",
+        ])
+    }
+
+    #[test]
+    fn should_narrow_substring_candidates_across_tag_files() {
+        let tag_database = indexed_database(&[
+            "ProjectileSpawnLocation\tGun.h\t/^\tFVector ProjectileSpawnLocation;$/;\"\tm\tline:3",
+            "Tick\tGun.h\t/^\tvirtual void Tick(float DeltaSeconds);$/;\"\tp\tline:5",
+            "GetSpawnLocation\tSpawner.h\t/^\tFVector GetSpawnLocation() const;$/;\"\tp\tline:8",
+        ]);
+
+        let names: Vec<&str> = tag_database.substring_candidates(&["SpawnLocation".to_string()], false)
+            .map(|v| v.name()).collect();
+        assert_eq!(vec!["ProjectileSpawnLocation", "GetSpawnLocation"], names);
+
+        let names: Vec<&str> = tag_database.substring_candidates(&["DeltaSeconds".to_string()], true)
+            .map(|v| v.name()).collect();
+        assert_eq!(vec!["Tick"], names);
+
+        assert_eq!("GetSpawnLocation", tag_database.tag(2).unwrap().name());
+        assert!(tag_database.tag(3).is_none());
+    }
+
+    #[test]
+    fn should_prefer_higher_priority_then_nearby_tags() {
+        let mut tag_database = TagDatabase::new();
+        for (priority, lines) in [(0, ["Spawn\tEngine/Source/Runtime/World.h\t/^\tvoid Spawn();$/;\"\tp\tline:3",
+                                       "Spawn\tEngine/Source/Editor/Spawner.h\t/^\tvoid Spawn();$/;\"\tp\tline:8"]),
+                                  (10, ["Spawn\tGame/Source/Enemy/Enemy.h\t/^\tvoid Spawn();$/;\"\tp\tline:5",
+                                        "Spawn\tGame/Source/Weapons/Gun.h\t/^\tvoid Spawn();$/;\"\tp\tline:5"])].iter() {
+            let mut tag_file = TagFile::new();
+            tag_file.priority = *priority;
+            for line in lines {
+                tag_file.tags.push(TagDefinition::from_string(line.to_string()));
+            }
+            tag_database.add_tag_file(tag_file);
+        }
+
+        let best = |from_file| {
+            let preference = tag_database.preference(from_file);
+            tag_database.all_tags().max_by_key(|v| preference(v)).unwrap().source_file()
+        };
+        assert_eq!("Game/Source/Weapons/Gun.h", best(Some("Game/Source/Weapons/Gun.cpp")));
+        assert_eq!("Game/Source/Enemy/Enemy.h", best(Some("Game/Source/Enemy/Private/Enemy.cpp")));
+        let preference = tag_database.preference(Some("Engine/Source/Editor/Spawner.cpp"));
+        assert!(preference(&tag_database.tag_files[0].tags[1]) > preference(&tag_database.tag_files[0].tags[0]));
+    }
+
+    #[test]
+    fn should_measure_directory_distance() {
+        assert_eq!(0, directory_distance("Game/Source/Gun.h", "game\\source\\Gun.cpp"));
+        assert_eq!(3, directory_distance("Game/Source/Weapons/Gun.cpp", "Game/Source/Enemy/Private/Enemy.h"));
+    }
+
+    #[test]
+    fn should_find_innermost_scope() {
+        let tag_database = scope_database();
+        let (tag, start, end) = tag_database.scope_at("Game.h", 11).unwrap().unwrap();
+        assert_eq!(("Test", 3, 12), (tag.name(), start, end));
+    }
+
+    #[test]
+    fn should_fall_back_to_next_sibling_for_missing_end() {
+        let tag_database = scope_database();
+        let (tag, start, end) = tag_database.scope_at("Game.h", 6).unwrap().unwrap();
+        assert_eq!(("DoTest", 5, 8), (tag.name(), start, end));
+        let (tag, start, end) = tag_database.scope_at("Game.h", 20).unwrap().unwrap();
+        assert_eq!(("Helper", 14, 30), (tag.name(), start, end));
+    }
+
+    #[test]
+    fn should_find_no_scope_outside_tags() {
+        let tag_database = scope_database();
+        assert!(tag_database.scope_at("Game.h", 31).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_find_transitive_subclasses() {
+        let tag_database = test_database();
+        let names: Vec<&str> = tag_database.subclasses_of("Test").iter().map(|v| v.name()).collect();
+        assert_eq!(vec!["SubTest", "SubSubTest"], names);
+    }
+
+    #[test]
+    fn should_resolve_bases_in_enclosing_scopes() {
+        let tag_database = database(&[
+            "Base\tGame.h\t/^class Base {$/;\"\tc\tline:1",
+            "Base\tGame.h\t/^class Base {$/;\"\tc\tline:4\tnamespace:Game",
+            "Base\tGame.h\t/^class Base {$/;\"\tc\tline:7\tnamespace:Editor",
+            "Actor\tGame.h\t/^class Actor : public Base {$/;\"\tc\tline:9\tnamespace:Game::World\tinherits:Base",
+            "Global\tGame.h\t/^class Global : public ::Base {$/;\"\tc\tline:12\tinherits:::Base",
+        ]);
+        let names: Vec<String> = tag_database.subclasses_of("Game::Base").iter().map(|v| v.qualified_name()).collect();
+        assert_eq!(vec!["Game::World::Actor"], names);
+        assert!(tag_database.subclasses_of("Editor::Base").is_empty());
+        let names: Vec<String> = tag_database.base_classes_of("Global").iter().map(|v| v.qualified_name()).collect();
+        assert_eq!(vec!["Base"], names);
+    }
+
+    #[test]
+    fn should_resolve_files_by_longest_suffix() {
+        let tag_database = database(&[
+            "Actor.h\tGame/Actor.h\t1;\"\tF\tline:1",
+            "Actor.h\tEditor/Actor.h\t1;\"\tF\tline:1",
+            "Actor.h\tPlugins/Game/Actor.h\t1;\"\tF\tline:1",
+        ]);
+        assert_eq!(Ok("Game/Actor.h"), tag_database.resolve_file("Game/Actor.h"));
+        assert_eq!(Ok("Editor/Actor.h"), tag_database.resolve_file("/home/user/Project/Editor/Actor.h"));
+        assert_eq!(Ok("Plugins/Game/Actor.h"), tag_database.resolve_file("Project/Plugins/Game/Actor.h"));
+        assert_eq!(Ok("Spawner.h"), tag_database.resolve_file("Spawner.h"));
+        assert!(tag_database.resolve_file("Actor.h").is_err());
+        assert!(tag_database.tags_in_file("Actor.h").is_err());
+    }
+
+    #[test]
+    fn should_find_transitive_base_classes() {
+        let tag_database = test_database();
+        let names: Vec<&str> = tag_database.base_classes_of("SubSubTest").iter().map(|v| v.name()).collect();
+        assert_eq!(vec!["SubTest", "Test"], names);
+    }
+
+    #[test]
+    fn should_find_overrides_in_subclasses() {
+        let tag_database = test_database();
+        let overrides: Vec<String> = tag_database.find_overrides("Test", "PureVirtual").iter()
+            .map(|v| v.qualified_name()).collect();
+        assert_eq!(vec!["SubTest::PureVirtual", "SubSubTest::PureVirtual"], overrides);
+    }
+
+    #[test]
+    fn should_find_overridden_base_declaration() {
+        let tag_database = test_database();
+        let overridden: Vec<String> = tag_database.find_overridden("SubTest", "PureVirtual").iter()
+            .map(|v| v.qualified_name()).collect();
+        assert_eq!(vec!["Test::PureVirtual"], overridden);
     }
 }
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use search::split_words;
+
+/// The distinct tag names of the database, arranged for typo tolerant lookups: by the strings
+/// left after deleting up to `MAX_EDIT_DISTANCE` characters from the start of their lowercase
+/// form, so that only names sharing one with the query are compared, and by their lowercase
+/// form, so that reordered CamelCase words are found without a scan.
+pub struct NameIndex {
+    names: Vec<String>,
+    lowercase_names: Vec<Vec<char>>,
+    name_indices: HashMap<String, usize>,
+    names_by_deletion: HashMap<String, Vec<usize>>,
+    names_by_lowercase: HashMap<String, Vec<usize>>,
+}
+
+/// Names further than this many edits (insertions, deletions, substitutions or transpositions
+/// of adjacent characters) from the query are never suggested.
+pub const MAX_EDIT_DISTANCE: usize = 2;
+
+/// Only deletions from this many leading characters are indexed, which bounds the entries per
+/// name. Two names within the edit distance bound still share a deletion of their starts, as
+/// trimming the longer start is one more deletion on the side with fewer edits.
+const DELETION_PREFIX_LENGTH: usize = 7;
+
+impl NameIndex {
+    pub fn new() -> NameIndex {
+        NameIndex {
+            names: Vec::new(),
+            lowercase_names: Vec::new(),
+            name_indices: HashMap::new(),
+            names_by_deletion: HashMap::new(),
+            names_by_lowercase: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: &str) {
+        if self.name_indices.contains_key(name) {
+            return;
+        }
+
+        let index = self.names.len();
+        let lowercase_name = name.to_lowercase();
+        self.names.push(name.to_string());
+        self.name_indices.insert(name.to_string(), index);
+
+        for deletion in prefix_deletions(&lowercase_name) {
+            let indices = self.names_by_deletion.entry(deletion).or_default();
+            if indices.last() != Some(&index) {
+                indices.push(index);
+            }
+        }
+        self.lowercase_names.push(lowercase_name.chars().collect());
+        self.names_by_lowercase.entry(lowercase_name).or_default().push(index);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.name_indices.contains_key(name)
+    }
+
+    /// Up to `limit` names close to `query`, closest first. The query itself is never suggested.
+    pub fn suggest(&self, query: &str, limit: usize) -> Vec<&str> {
+        let query_chars: Vec<char> = query.to_lowercase().chars().collect();
+        let mut suggestions: Vec<(usize, usize)> = Vec::new();
+
+        // CamelCase words in another order count as a single edit
+        for permutation in word_permutations(query) {
+            if let Some(indices) = self.names_by_lowercase.get(&permutation) {
+                suggestions.extend(indices.iter().map(|&v| (1, v)));
+            }
+        }
+
+        let mut compared: HashSet<usize> = HashSet::new();
+        for deletion in prefix_deletions(&query.to_lowercase()) {
+            for &index in self.names_by_deletion.get(&deletion).into_iter().flatten() {
+                if !compared.insert(index) {
+                    continue;
+                }
+                if let Some(distance) = bounded_edit_distance(&query_chars, &self.lowercase_names[index], MAX_EDIT_DISTANCE) {
+                    suggestions.push((distance, index));
+                }
+            }
+        }
+
+        suggestions.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| self.names[a.1].cmp(&self.names[b.1])));
+        let mut result: Vec<&str> = Vec::new();
+        for (_, index) in suggestions {
+            let name = &self.names[index][..];
+            if name != query && !result.contains(&name) {
+                result.push(name);
+                if result.len() == limit {
+                    break;
+                }
+            }
+        }
+        result
+    }
+}
+
+/// The distinct strings left by deleting up to `MAX_EDIT_DISTANCE` characters from the first
+/// `DELETION_PREFIX_LENGTH` characters of `name`, the start itself included.
+fn prefix_deletions(name: &str) -> Vec<String> {
+    let mut deletions = vec![name.chars().take(DELETION_PREFIX_LENGTH).collect::<String>()];
+    let mut start = 0;
+    for _ in 0..MAX_EDIT_DISTANCE {
+        let end = deletions.len();
+        for i in start..end {
+            let chars: Vec<char> = deletions[i].chars().collect();
+            for j in 0..chars.len() {
+                let deletion: String = chars[..j].iter().chain(chars[j + 1..].iter()).collect();
+                if !deletions.contains(&deletion) {
+                    deletions.push(deletion);
+                }
+            }
+        }
+        start = end;
+    }
+    deletions
+}
+
+/// The lowercase forms of `query` with its CamelCase words reordered. Queries with many words
+/// only get adjacent words swapped, to keep the number of lookups small.
+fn word_permutations(query: &str) -> Vec<String> {
+    let words: Vec<String> = split_words(query).iter().map(|v| v.to_lowercase()).collect();
+    if words.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut permutations = Vec::new();
+    if words.len() <= 4 {
+        let mut order: Vec<usize> = (0..words.len()).collect();
+        permute(&mut order, 0, &mut |order| {
+            permutations.push(order.iter().map(|&v| &words[v][..]).collect::<String>());
+        });
+    } else {
+        for i in 0..words.len() - 1 {
+            let mut swapped = words.clone();
+            swapped.swap(i, i + 1);
+            permutations.push(swapped.concat());
+        }
+    }
+
+    let original = words.concat();
+    permutations.retain(|v| *v != original);
+    permutations
+}
+
+fn permute<F: FnMut(&[usize])>(order: &mut Vec<usize>, start: usize, visit: &mut F) {
+    if start == order.len() {
+        visit(order);
+        return;
+    }
+    for i in start..order.len() {
+        order.swap(start, i);
+        permute(order, start + 1, visit);
+        order.swap(start, i);
+    }
+}
+
+/// The optimal string alignment distance between `a` and `b`, or None when it exceeds `bound`.
+/// Rows are abandoned as soon as every entry is over the bound.
+fn bounded_edit_distance(a: &[char], b: &[char], bound: usize) -> Option<usize> {
+    if (a.len() as isize - b.len() as isize).unsigned_abs() > bound {
+        return None;
+    }
+
+    let mut previous_previous: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+
+    for i in 1..a.len() + 1 {
+        current[0] = i;
+        let mut row_minimum = current[0];
+        for j in 1..b.len() + 1 {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(previous_previous[j - 2] + 1);
+            }
+            current[j] = distance;
+            row_minimum = row_minimum.min(distance);
+        }
+        if row_minimum > bound {
+            return None;
+        }
+
+        ::std::mem::swap(&mut previous_previous, &mut previous);
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+
+    let distance = previous[b.len()];
+    if distance <= bound { Some(distance) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(value: &str) -> Vec<char> {
+        value.chars().collect()
+    }
+
+    fn test_index() -> NameIndex {
+        let mut index = NameIndex::new();
+        for name in &["SpawnActor", "SpawnActors", "ActorSpawn", "LocationSpawnProjectile", "DestroyActor", "Tick"] {
+            index.add(name);
+        }
+        index
+    }
+
+    #[test]
+    fn should_bound_edit_distance() {
+        assert_eq!(Some(0), bounded_edit_distance(&chars("tick"), &chars("tick"), 2));
+        assert_eq!(Some(1), bounded_edit_distance(&chars("tikc"), &chars("tick"), 2));
+        assert_eq!(Some(2), bounded_edit_distance(&chars("tck"), &chars("tikc"), 2));
+        assert_eq!(None, bounded_edit_distance(&chars("tack"), &chars("stuck"), 1));
+        assert_eq!(None, bounded_edit_distance(&chars("spawn"), &chars("destroy"), 2));
+    }
+
+    #[test]
+    fn should_suggest_close_names() {
+        let index = test_index();
+        assert_eq!(vec!["SpawnActor", "SpawnActors"], index.suggest("SpwanActor", 5));
+        assert_eq!(vec!["SpawnActor"], index.suggest("SpwanActor", 1));
+        assert_eq!(vec!["Tick"], index.suggest("tikc", 5));
+    }
+
+    #[test]
+    fn should_suggest_reordered_camel_case_words() {
+        let index = test_index();
+        assert_eq!(vec!["LocationSpawnProjectile"], index.suggest("ProjectileSpawnLocation", 5));
+        assert_eq!(vec!["ActorSpawn", "SpawnActors"], index.suggest("SpawnActor", 5));
+    }
+
+    #[test]
+    fn should_suggest_names_with_typos_past_the_indexed_start() {
+        let index = test_index();
+        assert_eq!(vec!["LocationSpawnProjectile"], index.suggest("LocationSpawnPorjectile", 5));
+        assert_eq!(vec!["LocationSpawnProjectile"], index.suggest("XLocatonSpawnProjectile", 5));
+        assert_eq!(vec!["DestroyActor"], index.suggest("DestryActr", 5));
+    }
+
+    #[test]
+    fn should_not_suggest_distant_names() {
+        let index = test_index();
+        assert!(index.suggest("Render", 5).is_empty());
+    }
+}
@@ -9,6 +9,13 @@ pub struct TagDefinition {
     declaration: (usize, usize),
     // file_name slice and line index
     location: ((usize, usize), usize),
+    // last line of the definition, from the `end:` field
+    end_line: Option<usize>,
+    // value of the `roles:` field, empty without one
+    roles: (usize, usize),
+    is_reference: bool,
+    // number given by the database the tag was added to
+    id: Option<u32>,
     kind: TagKind,
     fields: Vec<(usize, usize)>,
 }
@@ -28,6 +35,19 @@ impl TagDefinition {
         self.location.1
     }
 
+    pub fn end_line(&self) -> Option<usize> {
+        self.end_line
+    }
+
+    /// The number of the tag in the database it was added to, see `TagDatabase::add_tag_file`.
+    pub fn id(&self) -> Option<u32> {
+        self.id
+    }
+
+    pub fn set_id(&mut self, id: u32) {
+        self.id = Some(id);
+    }
+
     pub fn kind(&self) -> TagKind {
         self.kind.clone()
     }
@@ -35,13 +55,104 @@ impl TagDefinition {
     pub fn declaration(&self) -> &str {
         &self.original_line[self.declaration.0..self.declaration.1]
     }
+
+    pub fn field(&self, field_name: &str) -> Option<&str> {
+        for field in &self.fields {
+            let slice = &self.original_line[field.0..field.1];
+            if let Some(separator) = slice.find(':') {
+                if &slice[..separator] == field_name {
+                    return Some(&slice[separator + 1..]);
+                }
+            }
+        }
+        None
+    }
+
+    /// The enclosing scope as a (kind, name) pair, e.g. ("class", "Test"). Handles both the
+    /// plain `class:Test` fields and the `scope:class:Test` form emitted with `--fields=+Z`.
+    pub fn scope(&self) -> Option<(&str, &str)> {
+        if let Some(scope) = self.field("scope") {
+            return match scope.find(':') {
+                None => Some(("", scope)),
+                Some(v) => Some((&scope[..v], &scope[v + 1..])),
+            };
+        }
+
+        for scope_kind in SCOPE_FIELDS.iter() {
+            if let Some(v) = self.field(scope_kind) {
+                return Some((scope_kind, v));
+            }
+        }
+        None
+    }
+
+    pub fn scope_name(&self) -> Option<&str> {
+        self.scope().map(|v| v.1)
+    }
+
+    pub fn qualified_name(&self) -> String {
+        match self.scope_name() {
+            None => self.name().to_string(),
+            Some(scope) => format!("{}::{}", scope, self.name()),
+        }
+    }
+
+    pub fn inherits(&self) -> Vec<&str> {
+        match self.field("inherits") {
+            None => Vec::new(),
+            Some(v) => v.split(',').map(|base| base.trim()).filter(|base| !base.is_empty()).collect(),
+        }
+    }
     
+    /// The roles of a reference tag (e.g. `system` or `local` for includes), from the `roles:`
+    /// field. Definitions either have no roles or the single `def` role.
+    pub fn roles(&self) -> Vec<&str> {
+        self.original_line[self.roles.0..self.roles.1].split(',').filter(|role| !role.is_empty()).collect()
+    }
+
+    pub fn is_reference(&self) -> bool {
+        self.is_reference
+    }
+
+    pub fn signature(&self) -> Option<&str> {
+        self.field("signature")
+    }
+
+    /// The signature reduced to its parameter types, so that a prototype and its definition
+    /// compare equal even when parameter names or default values differ.
+    pub fn normalized_signature(&self) -> Option<String> {
+        self.signature().map(normalize_signature)
+    }
+
+    pub fn is_class(&self) -> bool {
+        self.kind == TagKind::Class || self.kind == TagKind::Struct
+    }
+
+    /// Whether other tags can be scoped inside this one.
+    pub fn is_container(&self) -> bool {
+        matches!(self.kind, TagKind::Class | TagKind::Struct | TagKind::Union | TagKind::Enum | TagKind::Namespace)
+    }
+
+    pub fn is_method(&self) -> bool {
+        self.kind == TagKind::FunctionDefinition || self.kind == TagKind::FunctionPrototype
+    }
+
+    /// Whether the tag is located in `file_path`. Tags files often hold paths relative to the
+    /// directory ctags ran in, so a path matches when either one is a suffix of the other.
+    pub fn is_in_file(&self, file_path: &str) -> bool {
+        same_file(self.source_file(), file_path)
+    }
+
     pub fn new() -> TagDefinition {
         TagDefinition {
             original_line: String::new(),
             name: (0, 0),
             declaration: (0, 0),
             location: ((0, 0), 0),
+            end_line: None,
+            roles: (0, 0),
+            is_reference: false,
+            id: None,
             kind: TagKind::File,
             fields: Vec::new(),
         }
@@ -53,74 +164,157 @@ impl TagDefinition {
             name: (0, file_path.len()),
             declaration: (0, 0),
             location: ((0, 0), 0),
+            end_line: None,
+            roles: (0, 0),
+            is_reference: false,
+            id: None,
             kind: TagKind::File,
             fields: Vec::new(),
         }
     }
 
+    /// The tag on a line of a tags file. Panics if the line is malformed, see `parse`.
     pub fn from_string(tag_definition: String) -> TagDefinition {
+        TagDefinition::parse(tag_definition).expect("malformed tag line")
+    }
+
+    /// The tag on a line of a tags file, or None if the line is malformed.
+    pub fn parse(tag_definition: String) -> Option<TagDefinition> {
         let mut cursor = 0;
         let mut token_end;
 
         // name
-        token_end = tag_definition.find("\t").unwrap();
+        token_end = tag_definition.find('\t')?;
         let name = (cursor, token_end);
         cursor = token_end + 1;
 
         // file
-        token_end = tag_definition[cursor..].find("\t").unwrap() + cursor;
+        token_end = tag_definition[cursor..].find('\t')? + cursor;
         let location_file_path = (cursor, token_end);
 
         // declaration
-        let (declaration, mut cursor) = parse_declaration(&tag_definition[..]);
+        let (declaration, mut cursor) = parse_declaration(&tag_definition[..])?;
 
         // kind
-        let tag_kind_char = tag_definition.chars().nth(cursor).unwrap();
-        cursor = cursor + 1;
+        let tag_kind_char = tag_definition.get(cursor..)?.chars().next()?;
+        cursor += tag_kind_char.len_utf8();
 
         // fields
-        let fields: Vec<(usize, usize)> = tuples_from_split(&tag_definition[cursor + 1..], "\t")
+        let fields: Vec<(usize, usize)> = tuples_from_split(tag_definition.get(cursor + 1..).unwrap_or(""), "\t")
             .iter().map(|f| (cursor + 1 + f.0, cursor + 1 + f.1)).collect();
 
+        // end line (from fields)
+        let end_line = fields.iter()
+            .find(|x| tag_definition[(x.0)..(x.1)].starts_with("end:"))
+            .and_then(|v| tag_definition[(v.0 + 4)..(v.1)].parse::<usize>().ok());
+
+        // roles (from fields), a tag is a reference unless it has none or the `def` role
+        let roles = fields.iter()
+            .find(|x| tag_definition[(x.0)..(x.1)].starts_with("roles:"))
+            .map(|v| (v.0 + 6, v.1))
+            .unwrap_or((0, 0));
+        let is_reference = {
+            let mut roles = tag_definition[roles.0..roles.1].split(',').filter(|role| !role.is_empty()).peekable();
+            roles.peek().is_some() && roles.all(|role| role != "def")
+        };
+
         // line number (from fields)
         let location_line = {
             let line_field = fields.iter().find(|x| tag_definition[(x.0)..(x.1)].starts_with("line:"));
             match line_field {
-                None => 1 as usize,
-                Some(v) => {
-                    let slice = &tag_definition[(v.0)..(v.1)];
-                    slice[slice.find(":").unwrap() + 1..].parse::<usize>().unwrap()
-                }
+                None => 1,
+                Some(v) => tag_definition[(v.0 + 5)..(v.1)].parse::<usize>().ok()?,
             }
         };
 
-        TagDefinition {
+        Some(TagDefinition {
             original_line: tag_definition,
             name,
             declaration,
             location: (location_file_path, location_line),
+            end_line,
+            roles,
+            is_reference,
+            id: None,
             kind: tag_kind_from_char(tag_kind_char),
             fields,
-        }
+        })
     }
+}
 
-    pub fn to_elisp(&self) -> String {
-        format!("(tag :name \"{}\" :source \"{}\" line: {} :kind '{:?}))", self.name(),
-                self.source_file(), self.source_line(), self.kind())
-    }
+const SCOPE_FIELDS: [&str; 6] = ["class", "struct", "union", "namespace", "enum", "function"];
+
+pub fn same_file(left: &str, right: &str) -> bool {
+    let left = left.replace('\\', "/");
+    let right = right.replace('\\', "/");
+    left == right || left.ends_with(&format!("/{}", right)) || right.ends_with(&format!("/{}", left))
 }
 
-fn parse_declaration(line: &str) -> ((usize, usize), usize) {
-    let declaration_start_find = line.find("/^");
-    match declaration_start_find {
-        None => {
-            ((0, 0), line.find(";\"").unwrap() + 3)
-        }
-        Some(v) => {
-            let declaration_end = line.find(";\"").unwrap();
-            ((v + 2, declaration_end - 2), declaration_end + 3)
+fn normalize_signature(signature: &str) -> String {
+    let (parameters, qualifiers) = match (signature.find('('), signature.rfind(')')) {
+        (Some(open), Some(close)) if open < close => (&signature[open + 1..close], &signature[close + 1..]),
+        _ => (signature, ""),
+    };
+
+    let mut normalized_parameters = Vec::new();
+    let mut depth = 0;
+    let mut parameter_start = 0;
+    for (i, c) in parameters.char_indices() {
+        match c {
+            '(' | '<' | '[' => depth += 1,
+            ')' | '>' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                normalized_parameters.push(normalize_parameter(&parameters[parameter_start..i]));
+                parameter_start = i + 1;
+            }
+            _ => {}
         }
     }
+    normalized_parameters.push(normalize_parameter(&parameters[parameter_start..]));
+    normalized_parameters.retain(|v| !v.is_empty() && v != "void");
+
+    format!("({}){}", normalized_parameters.join(","), qualifiers.split_whitespace().collect::<Vec<&str>>().join(" "))
+}
+
+/// Drops the default value and the parameter name, keeping only the type.
+fn normalize_parameter(parameter: &str) -> String {
+    let parameter = match parameter.find('=') {
+        None => parameter,
+        Some(v) => &parameter[..v],
+    }.trim();
+
+    let name_start = parameter.rfind(|c: char| !(c.is_alphanumeric() || c == '_')).map(|v| v + 1).unwrap_or(0);
+    let name = &parameter[name_start..];
+    let type_part = parameter[..name_start].trim();
+    let parameter = if name_start > 0 && is_complete_type(type_part) && !TYPE_KEYWORDS.contains(&name) {
+        type_part
+    } else {
+        parameter
+    };
+
+    parameter.split_whitespace().collect::<Vec<&str>>().join(" ")
+        .replace(" *", "*").replace(" &", "&")
+}
+
+const TYPE_KEYWORDS: [&str; 11] = ["int", "char", "float", "double", "bool", "long", "short", "unsigned",
+                                    "signed", "const", "void"];
+
+const TYPE_QUALIFIERS: [&str; 8] = ["const", "volatile", "struct", "class", "enum", "union", "typename", "mutable"];
+
+/// Whether `type_part` names a type on its own, so the identifier after it is a parameter name.
+/// It does not for a bare qualifier like `const` in `const FString` or a scope like `Game::`.
+fn is_complete_type(type_part: &str) -> bool {
+    !type_part.ends_with("::") && type_part.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .any(|v| !v.is_empty() && !TYPE_QUALIFIERS.contains(&v))
+}
+
+fn parse_declaration(line: &str) -> Option<((usize, usize), usize)> {
+    let declaration_end = line.find(";\"")?;
+    match line.find("/^") {
+        None => Some(((0, 0), declaration_end + 3)),
+        Some(v) if v + 2 <= declaration_end.saturating_sub(2) => Some(((v + 2, declaration_end - 2), declaration_end + 3)),
+        Some(_) => None,
+    }
 }
 
 fn tuples_from_split(value: &str, separator: &str) -> Vec<(usize, usize)> {
@@ -136,9 +330,9 @@ fn tuples_from_split(value: &str, separator: &str) -> Vec<(usize, usize)> {
     let mut slices: Vec<(usize, usize)> = Vec::new();
     slices.push((0, separator_indices[0]));
     for i in 0..(separator_indices.len()-1) {
-        slices.push((separator_indices[i], separator_indices[i+1]))
+        slices.push((separator_indices[i] + separator.len(), separator_indices[i+1]))
     }
-    slices.push((*separator_indices.last().unwrap(), value.len()));
+    slices.push((*separator_indices.last().unwrap() + separator.len(), value.len()));
     slices
 }
 
@@ -158,6 +352,15 @@ mod tests {
         assert_eq!("", tag_definition.declaration());
     }
 
+    #[test]
+    fn should_reject_malformed_lines() {
+        assert!(TagDefinition::parse("Test".to_string()).is_none());
+        assert!(TagDefinition::parse("Test\tTest.h\t/^class Test {$/".to_string()).is_none());
+        assert!(TagDefinition::parse("Test\tTest.h\t/^class Test {$/;\"".to_string()).is_none());
+        assert!(TagDefinition::parse("Test\tTest.h\t/^class Test {$/;\"\tc\tline:x".to_string()).is_none());
+        assert!(TagDefinition::parse("Test\tTest.h\t/^class Test {$/;\"\tc".to_string()).is_some());
+    }
+
     #[test]
     fn should_parse_class_declaration() {
         let definition_str = "Test\tTest.h\t/^class Test {$/;\"\tc\tline:13".to_string();
@@ -169,4 +372,59 @@ mod tests {
         assert_eq!("class Test {", tag_definition.declaration());
     }
 
+    #[test]
+    fn should_parse_fields_after_the_first() {
+        let definition_str = "PureVirtual\tTest.h\t/^\tvirtual void PureVirtual() = 0;$/;\"\tp\tclass:Test\ttyperef:typename:void\tline:18".to_string();
+        let tag_definition = TagDefinition::from_string(definition_str);
+        assert_eq!(18, tag_definition.source_line());
+        assert_eq!(Some("typename:void"), tag_definition.field("typeref"));
+        assert_eq!(Some(("class", "Test")), tag_definition.scope());
+        assert_eq!("Test::PureVirtual", tag_definition.qualified_name());
+    }
+
+    #[test]
+    fn should_parse_scope_and_inheritance() {
+        let definition_str = "SubTest\tTest.h\t/^class SubTest : public Test, Other {$/;\"\tc\tline:21\tscope:namespace:Game\tinherits:Test,Other".to_string();
+        let tag_definition = TagDefinition::from_string(definition_str);
+        assert_eq!(Some(("namespace", "Game")), tag_definition.scope());
+        assert_eq!("Game::SubTest", tag_definition.qualified_name());
+        assert_eq!(vec!["Test", "Other"], tag_definition.inherits());
+    }
+
+    #[test]
+    fn should_parse_reference_roles() {
+        let definition_str = "stdio.h\tTest.c\t/^#include <stdio.h>$/;\"\th\tline:1\troles:system".to_string();
+        let tag_definition = TagDefinition::from_string(definition_str);
+        assert_eq!(vec!["system"], tag_definition.roles());
+        assert!(tag_definition.is_reference());
+
+        let definition_str = "Test\tTest.h\t/^class Test {$/;\"\tc\tline:13\troles:def".to_string();
+        assert!(!TagDefinition::from_string(definition_str).is_reference());
+    }
+
+    #[test]
+    fn should_parse_end_line() {
+        let definition_str = "DoTest\tTest.h\t/^\tint DoTest() const {$/;\"\tf\tline:16\tclass:Test\tend:19".to_string();
+        let tag_definition = TagDefinition::from_string(definition_str);
+        assert_eq!(16, tag_definition.source_line());
+        assert_eq!(Some(19), tag_definition.end_line());
+    }
+
+    #[test]
+    fn should_normalize_signatures() {
+        assert_eq!("(int,const FString&)const", normalize_signature("(int Count, const FString& Name = TEXT(\"\")) const"));
+        assert_eq!("(int,const FString&)const", normalize_signature("(int, const FString &)  const"));
+        assert_eq!("(AActor*,unsigned int)", normalize_signature("(AActor * Owner, unsigned int)"));
+        assert_eq!("()", normalize_signature("(void)"));
+        assert_eq!("(const FString,struct FHitResult&)", normalize_signature("(const FString, struct FHitResult &)"));
+        assert_eq!("(const FString,TArray<int>)", normalize_signature("(const FString Name, TArray<int> Values)"));
+    }
+
+    #[test]
+    fn should_match_relative_paths() {
+        assert!(same_file("test/Test.h", "/home/user/enigma/test/Test.h"));
+        assert!(same_file("D:\\Source\\Test.h", "D:/Source/Test.h"));
+        assert!(!same_file("test/Test.h", "/home/user/enigma/test/SubTest.h"));
+    }
+
 }
@@ -1,14 +1,20 @@
 use std::fs::File;
+use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::time::Duration;
 use std::time::Instant;
 
 use tags::tag_definition::TagDefinition;
 
-#[allow(dead_code)]
 pub struct TagFile {
     file_path: String,
-    pub tags: Vec<TagDefinition>
+    pub tags: Vec<TagDefinition>,
+    /// Tags from files with a higher priority are preferred over same-named tags from others,
+    /// e.g. project tags over engine tags.
+    pub priority: i32,
+    /// How long reading and parsing the file took.
+    pub load_duration: Duration,
 }
 
 impl TagFile {
@@ -17,16 +23,21 @@ impl TagFile {
         TagFile {
             file_path: "".to_string(),
             tags: Vec::new(),
+            priority: 0,
+            load_duration: Duration::from_secs(0),
         }
     }
 
-    pub fn from_file(input_file_path: &str) -> TagFile {
+    /// The tags in a tags file. Malformed lines are skipped with a warning.
+    pub fn from_file(input_file_path: &str) -> io::Result<TagFile> {
         let mut result = TagFile {
             file_path: String::from(input_file_path),
             tags: Vec::new(),
+            priority: 0,
+            load_duration: Duration::from_secs(0),
         };
 
-        let f = File::open(input_file_path).unwrap();
+        let f = File::open(input_file_path)?;
         let reader = BufReader::new(&f);
         let now = Instant::now();
         for (num, line) in reader.lines().enumerate() {
@@ -36,22 +47,27 @@ impl TagFile {
                 }
                 Ok(v) => {
                     if !v.starts_with("!_") {
-                        result.tags.push(TagDefinition::from_string(v));
+                        match TagDefinition::parse(v) {
+                            None => warn!("Skipping malformed tag on line {}", num + 1),
+                            Some(tag) => result.tags.push(tag),
+                        }
                     }
                 }
             }
         }        
         let elapsed = now.elapsed();
+        result.load_duration = elapsed;
         info!("Finished parsing {} tags file in {:.3}s", result.tags.len(), elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9_f64);
-        result
+        Ok(result)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.file_path
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use std::{thread, time};
-    
     #[test]
     fn should_parse_simple_file() {
     }
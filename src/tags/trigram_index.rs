@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+/// Maps every lowercase three character sequence to the ids of the tags whose text contains it.
+/// A substring can only occur in tags holding all of its trigrams, so intersecting their
+/// posting lists leaves a small set of candidates to verify.
+pub struct TrigramIndex {
+    postings: HashMap<u64, Vec<u32>>,
+}
+
+impl TrigramIndex {
+    pub fn new() -> TrigramIndex {
+        TrigramIndex {
+            postings: HashMap::new(),
+        }
+    }
+
+    /// Indexes `text` under `tag_id`. Ids must be added in increasing order.
+    pub fn add(&mut self, tag_id: u32, text: &str) {
+        for trigram in trigrams(text) {
+            let posting = self.postings.entry(trigram).or_default();
+            if posting.last() != Some(&tag_id) {
+                posting.push(tag_id);
+            }
+        }
+    }
+
+    /// The ids of the tags whose text may contain every one of `literals`, in increasing order.
+    /// None when no literal is long enough to narrow the search, so that every tag is a candidate.
+    pub fn candidates(&self, literals: &[String]) -> Option<Vec<u32>> {
+        let mut query_trigrams: Vec<u64> = literals.iter().flat_map(|v| trigrams(v)).collect();
+        if query_trigrams.is_empty() {
+            return None;
+        }
+
+        // intersect the shortest posting lists first
+        query_trigrams.sort_by_key(|v| self.postings.get(v).map(|p| p.len()).unwrap_or(0));
+        query_trigrams.dedup();
+
+        let mut result: Vec<u32> = match self.postings.get(&query_trigrams[0]) {
+            None => return Some(Vec::new()),
+            Some(v) => v.clone(),
+        };
+        for trigram in &query_trigrams[1..] {
+            let posting = match self.postings.get(trigram) {
+                None => return Some(Vec::new()),
+                Some(v) => v,
+            };
+            result = intersect(&result, posting);
+            if result.is_empty() {
+                break;
+            }
+        }
+        Some(result)
+    }
+}
+
+fn trigrams(text: &str) -> Vec<u64> {
+    let chars: Vec<char> = text.chars().flat_map(|c| c.to_lowercase()).collect();
+    chars.windows(3)
+        .map(|v| ((v[0] as u64) << 42) | ((v[1] as u64) << 21) | (v[2] as u64))
+        .collect()
+}
+
+fn intersect(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        if left[i] < right[j] {
+            i += 1;
+        } else if left[i] > right[j] {
+            j += 1;
+        } else {
+            result.push(left[i]);
+            i += 1;
+            j += 1;
+        }
+    }
+    result
+}
+
+/// Literal strings every match of the regular expression `pattern` must contain. This is a
+/// conservative scan: anything it does not understand ends the current literal, and patterns
+/// with alternations or case-insensitive flags yield no literals at all.
+pub fn regex_literals(pattern: &str) -> Vec<String> {
+    let mut literals = Vec::new();
+    if pattern.contains('|') || pattern.contains("(?") {
+        return literals;
+    }
+
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut literal = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).cloned();
+        let (character, length) = match c {
+            '\\' => match next {
+                Some(escaped) if !escaped.is_alphanumeric() => (Some(escaped), 2),
+                _ => (None, escape_end(&chars, i) - i),
+            },
+            '[' => (None, class_end(&chars, i) - i),
+            '(' => {
+                // groups may be optional or repeated, so their contents are skipped
+                let mut depth = 0;
+                let mut end = i;
+                while end < chars.len() {
+                    match chars[end] {
+                        '\\' => end = escape_end(&chars, end),
+                        '[' => end = class_end(&chars, end),
+                        '(' => {
+                            depth += 1;
+                            end += 1;
+                        }
+                        ')' => {
+                            depth -= 1;
+                            end += 1;
+                        }
+                        _ => end += 1,
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                (None, end - i)
+            }
+            '.' | '^' | '$' | ')' | '+' => (None, 1),
+            '*' | '?' | '{' => {
+                // the quantified character was already left out of the literal
+                flush_literal(&mut literal, &mut literals);
+                let mut end = i + 1;
+                if c == '{' {
+                    while end < chars.len() && chars[end] != '}' {
+                        end += 1;
+                    }
+                    end += 1;
+                }
+                i = end;
+                continue;
+            }
+            _ => (Some(c), 1),
+        };
+
+        let quantified = matches!(chars.get(i + length), Some(&'*') | Some(&'?') | Some(&'{'));
+        match character {
+            Some(v) if !quantified => literal.push(v),
+            Some(_) => flush_literal(&mut literal, &mut literals),
+            None => flush_literal(&mut literal, &mut literals),
+        }
+        if c == '+' {
+            flush_literal(&mut literal, &mut literals);
+        }
+        i += length;
+    }
+    flush_literal(&mut literal, &mut literals);
+
+    literals
+}
+
+/// The index after the escape sequence starting with the backslash at `start`, e.g. after all
+/// of `\x41`, `\u{1F600}` or `\p{Greek}`.
+fn escape_end(chars: &[char], start: usize) -> usize {
+    let fixed_length = match chars.get(start + 1) {
+        Some(&'x') => 2,
+        Some(&'u') => 4,
+        Some(&'U') => 8,
+        Some(&'p') | Some(&'P') => 1,
+        _ => return (start + 2).min(chars.len()),
+    };
+    if chars.get(start + 2) == Some(&'{') {
+        let mut end = start + 3;
+        while end < chars.len() && chars[end] != '}' {
+            end += 1;
+        }
+        return (end + 1).min(chars.len());
+    }
+    (start + 2 + fixed_length).min(chars.len())
+}
+
+/// The index after the character class opened at `start`. A `]` right after the opening `[` or
+/// `[^` is a literal, and classes nest, as in `[[:alpha:]_]`.
+fn class_end(chars: &[char], start: usize) -> usize {
+    let mut end = start + 1;
+    if chars.get(end) == Some(&'^') {
+        end += 1;
+    }
+    if chars.get(end) == Some(&']') {
+        end += 1;
+    }
+
+    let mut depth = 1;
+    while end < chars.len() {
+        match chars[end] {
+            '\\' => {
+                end = escape_end(chars, end);
+                continue;
+            }
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return end + 1;
+                }
+            }
+            _ => (),
+        }
+        end += 1;
+    }
+    chars.len()
+}
+
+fn flush_literal(literal: &mut String, literals: &mut Vec<String>) {
+    if literal.chars().count() >= 3 {
+        literals.push(literal.clone());
+    }
+    literal.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn test_index() -> TrigramIndex {
+        let mut index = TrigramIndex::new();
+        index.add(0, "ProjectileSpawnLocation");
+        index.add(1, "SpawnActor");
+        index.add(2, "GetSpawnLocation");
+        index.add(3, "Tick");
+        index
+    }
+
+    #[test]
+    fn should_narrow_candidates_by_substring() {
+        let index = test_index();
+        assert_eq!(Some(vec![0, 2]), index.candidates(&["spawnlocation".to_string()]));
+        assert_eq!(Some(vec![0, 1, 2]), index.candidates(&["Spawn".to_string()]));
+        assert_eq!(Some(vec![]), index.candidates(&["Render".to_string()]));
+        assert_eq!(None, index.candidates(&["Sp".to_string()]));
+    }
+
+    #[test]
+    fn should_extract_required_regex_literals() {
+        assert_eq!(vec!["Get", "Component"], regex_literals("^Get.*Component$"));
+        assert_eq!(vec!["UPROPERTY("], regex_literals("UPROPERTY\\(.*\\)"));
+        assert_eq!(vec!["Spawn", "Location"], regex_literals("Spawns?Location"));
+        assert_eq!(vec!["Actor"], regex_literals("[AU]Actor\\w+"));
+        assert_eq!(vec!["Bar"], regex_literals("(Foo)?Bar"));
+        assert!(regex_literals("Get|Set").is_empty());
+        assert!(regex_literals("(?i)spawn").is_empty());
+    }
+
+    #[test]
+    fn should_skip_escape_sequences_and_classes_in_regex_literals() {
+        assert_eq!(vec!["BCD"], regex_literals("\\x41BCD"));
+        assert_eq!(vec!["Spawn"], regex_literals("\\u{41}Spawn"));
+        assert_eq!(vec!["Actor"], regex_literals("\\p{Lu}Actor"));
+        assert_eq!(vec!["Actor"], regex_literals("\\pLActor"));
+        assert_eq!(vec!["Spawn"], regex_literals("[]x]Spawn"));
+        assert_eq!(vec!["Spawn"], regex_literals("[^]x]Spawn"));
+        assert_eq!(vec!["Spawn"], regex_literals("[[:upper:]]Spawn"));
+        assert_eq!(vec!["Spawn"], regex_literals("([)])?Spawn"));
+    }
+
+    #[test]
+    fn should_find_what_a_regex_scan_finds() {
+        let names = ["ProjectileSpawnLocation", "GetSpawnLocation", "SpawnActor", "ASpawner", "Tick", "x]Spawn",
+                     "]Spawn", "ABCD", "\u{41}Spawn", "Actor_Spawn", "TArray<int>", "UPROPERTY()", "aaSpawn"];
+        let mut index = TrigramIndex::new();
+        for (i, name) in names.iter().enumerate() {
+            index.add(i as u32, name);
+        }
+
+        for pattern in &["Spawn", "^Spawn", "Spawn[A-Z]", "\\x41BCD", "\\x{41}BCD", "[]x]Spawn", "\\]Spawn",
+                         "\\p{Lu}ctor", "\\u{41}Spawn", "Spawns?Location", "(Get|Projectile)?Spawn", "TArray<\\w+>",
+                         "UPROPERTY\\(\\)", "Actor_?Spawn", "a{2}Spawn", "[[:upper:]]Spawn", "Tick$"] {
+            let regex = Regex::new(pattern).unwrap();
+            let expected: Vec<u32> = (0..names.len() as u32).filter(|&v| regex.is_match(names[v as usize])).collect();
+            let found: Vec<u32> = match index.candidates(&regex_literals(pattern)) {
+                None => (0..names.len() as u32).collect(),
+                Some(candidates) => candidates,
+            };
+            let found: Vec<u32> = found.into_iter().filter(|&v| regex.is_match(names[v as usize])).collect();
+            assert_eq!(expected, found, "pattern {}", pattern);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use tags::TagDefinition;
+use tags::TagKind;
+use tags::tag_kind_from_str;
+use tags::tag_kind_name;
+
+/// The tags each client jumped to, how often and how recently. Tags are identified by file,
+/// name and kind, which stay the same when tags files are regenerated or reloaded.
+pub struct VisitHistory {
+    /// The visited tags by client, then by name, which is the most selective part of their
+    /// identity.
+    visits: HashMap<String, HashMap<String, Vec<Visits>>>,
+    history_file: Option<PathBuf>,
+}
+
+struct Visits {
+    file: String,
+    kind: TagKind,
+    count: u32,
+    last_visit: u64,
+}
+
+/// The most a visited tag's match score is raised by, less than the gap between a prefix and
+/// an abbreviation match, so frecency only reorders similarly good matches.
+pub const MAX_FRECENCY_BOOST: usize = 200;
+
+/// Tags not visited for this long are forgotten.
+const MAX_VISIT_AGE: u64 = 365 * DAY;
+
+/// Only this many of the most recently visited tags are remembered.
+const MAX_VISITED_TAGS: usize = 10000;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// The client of requests which don't name one.
+pub const DEFAULT_CLIENT: &str = "default";
+
+impl VisitHistory {
+    pub fn new() -> VisitHistory {
+        VisitHistory {
+            visits: HashMap::new(),
+            history_file: None,
+        }
+    }
+
+    /// The history stored in `history_file`, which every visit is saved back to. A missing
+    /// file is an empty history, and malformed lines are skipped.
+    pub fn load(history_file: PathBuf) -> VisitHistory {
+        let mut history = VisitHistory::new();
+        if let Ok(file) = File::open(&history_file) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                let columns: Vec<&str> = line.splitn(6, '\t').collect();
+                if columns.len() != 6 {
+                    warn!("Skipping malformed visit history line \"{}\"", line);
+                    continue;
+                }
+                let (count, last_visit) = match (columns[0].parse::<u32>(), columns[1].parse::<u64>()) {
+                    (Ok(count), Ok(last_visit)) => (count, last_visit),
+                    _ => {
+                        warn!("Skipping malformed visit history line \"{}\"", line);
+                        continue;
+                    }
+                };
+                let client_visits = history.visits.entry(columns[3].to_string()).or_default();
+                client_visits.entry(columns[4].to_string()).or_default().push(Visits {
+                    file: columns[5].to_string(),
+                    kind: tag_kind_from_str(columns[2]),
+                    count,
+                    last_visit,
+                });
+            }
+        }
+        history.history_file = Some(history_file);
+        history
+    }
+
+    /// Records a visit of `client` to `tag`.
+    pub fn record(&mut self, client: &str, tag: &TagDefinition) {
+        let time = now();
+        self.record_at(client, tag, time);
+        self.prune(time);
+        self.save();
+    }
+
+    /// How much to raise the match score of `tag` for `client`, from how often and how recently
+    /// that client visited it, up to `MAX_FRECENCY_BOOST`.
+    pub fn frecency(&self, client: &str, tag: &TagDefinition) -> usize {
+        self.frecency_at(client, tag, now())
+    }
+
+    fn record_at(&mut self, client: &str, tag: &TagDefinition, time: u64) {
+        let client_visits = self.visits.entry(client.to_string()).or_default();
+        if let Some(visits) = client_visits.get_mut(tag.name()).and_then(|v| v.iter_mut().find(|v| v.is_of(tag))) {
+            visits.count += 1;
+            visits.last_visit = time;
+            return;
+        }
+        client_visits.entry(tag.name().to_string()).or_default().push(Visits {
+            file: tag.source_file().to_string(),
+            kind: tag.kind(),
+            count: 1,
+            last_visit: time,
+        });
+    }
+
+    fn frecency_at(&self, client: &str, tag: &TagDefinition, time: u64) -> usize {
+        let visits = self.visits.get(client)
+            .and_then(|v| v.get(tag.name()))
+            .and_then(|v| v.iter().find(|v| v.is_of(tag)));
+        let visits = match visits {
+            None => return 0,
+            Some(v) => v,
+        };
+
+        let age = time.saturating_sub(visits.last_visit);
+        let weight = match age / DAY {
+            0..=3 => 100,
+            4..=13 => 70,
+            14..=30 => 50,
+            31..=89 => 30,
+            _ => 10,
+        };
+        (visits.count as usize * weight / 10).min(MAX_FRECENCY_BOOST)
+    }
+
+    /// Forgets the tags not visited for `MAX_VISIT_AGE`, and all but the `MAX_VISITED_TAGS`
+    /// most recently visited ones of all clients.
+    fn prune(&mut self, time: u64) {
+        let mut last_visits: Vec<u64> = self.visits.values().flat_map(|v| v.values()).flatten().map(|v| v.last_visit).collect();
+        let mut oldest_kept = time.saturating_sub(MAX_VISIT_AGE);
+        if last_visits.len() > MAX_VISITED_TAGS {
+            let (_, &mut nth_newest, _) = last_visits.select_nth_unstable_by(MAX_VISITED_TAGS - 1, |a, b| b.cmp(a));
+            oldest_kept = oldest_kept.max(nth_newest);
+        }
+
+        for client_visits in self.visits.values_mut() {
+            for visits in client_visits.values_mut() {
+                visits.retain(|v| v.last_visit >= oldest_kept);
+            }
+            client_visits.retain(|_, v| !v.is_empty());
+        }
+        self.visits.retain(|_, v| !v.is_empty());
+    }
+
+    /// Writes the history to a temporary file next to the history file, then renames it over
+    /// the history file, so that a crash while saving never leaves a truncated history.
+    fn save(&self) {
+        let history_file = match self.history_file {
+            None => return,
+            Some(ref v) => v,
+        };
+
+        let mut contents = String::new();
+        for (client, client_visits) in &self.visits {
+            for (name, visits) in client_visits {
+                for v in visits {
+                    contents.push_str(&format!("{}\t{}\t{}\t{}\t{}\t{}\n",
+                                               v.count, v.last_visit, tag_kind_name(&v.kind), client, name, v.file));
+                }
+            }
+        }
+        let temporary_file = history_file.with_extension("tmp");
+        let result = File::create(&temporary_file)
+            .and_then(|mut v| v.write_all(contents.as_bytes()).and_then(|_| v.sync_all()))
+            .and_then(|_| fs::rename(&temporary_file, history_file));
+        if let Err(e) = result {
+            warn!("Failed to save visit history to {}: {}", history_file.display(), e);
+        }
+    }
+}
+
+impl Visits {
+    fn is_of(&self, tag: &TagDefinition) -> bool {
+        self.kind == tag.kind() && self.file == tag.source_file()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|v| v.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn tag(line: &str) -> TagDefinition {
+        TagDefinition::from_string(line.to_string())
+    }
+
+    #[test]
+    fn should_boost_frequent_recent_visits() {
+        let tick = tag("Tick\tActor.h\t/^\tvoid Tick();$/;\"\tp\tline:50\tclass:AActor");
+        let tick_definition = tag("Tick\tActor.cpp\t/^void AActor::Tick()$/;\"\tf\tline:80\tclass:AActor");
+        let time = 100 * DAY;
+
+        let mut history = VisitHistory::new();
+        history.record_at(DEFAULT_CLIENT, &tick, time);
+        history.record_at(DEFAULT_CLIENT, &tick, time);
+        assert_eq!(20, history.frecency_at(DEFAULT_CLIENT, &tick, time));
+        assert_eq!(6, history.frecency_at(DEFAULT_CLIENT, &tick, time + 40 * DAY));
+        assert_eq!(0, history.frecency_at(DEFAULT_CLIENT, &tick_definition, time));
+
+        for _ in 0..100 {
+            history.record_at(DEFAULT_CLIENT, &tick, time);
+        }
+        assert_eq!(MAX_FRECENCY_BOOST, history.frecency_at(DEFAULT_CLIENT, &tick, time));
+    }
+
+    #[test]
+    fn should_boost_only_the_visits_of_the_same_client() {
+        let tick = tag("Tick\tActor.h\t/^\tvoid Tick();$/;\"\tp\tline:50\tclass:AActor");
+        let time = 100 * DAY;
+
+        let mut history = VisitHistory::new();
+        history.record_at("emacs", &tick, time);
+        assert_eq!(10, history.frecency_at("emacs", &tick, time));
+        assert_eq!(0, history.frecency_at("shell", &tick, time));
+        assert_eq!(0, history.frecency_at(DEFAULT_CLIENT, &tick, time));
+    }
+
+    #[test]
+    fn should_persist_visits() {
+        let history_file = env::temp_dir().join(format!("enigma-history-test-{}", ::std::process::id()));
+        let tick = tag("Tick\tActor.h\t/^\tvoid Tick();$/;\"\tp\tline:50\tclass:AActor");
+
+        let mut history = VisitHistory::load(history_file.clone());
+        history.record("emacs", &tick);
+        // the same tag from a reloaded tags file
+        let reloaded_tick = tag("Tick\tActor.h\t/^\tvoid Tick();$/;\"\tp\tline:52\tclass:AActor");
+        let reloaded_history = VisitHistory::load(history_file.clone());
+        assert_eq!(10, reloaded_history.frecency("emacs", &reloaded_tick));
+        assert_eq!(0, reloaded_history.frecency(DEFAULT_CLIENT, &reloaded_tick));
+
+        fs::remove_file(history_file).unwrap();
+    }
+
+    #[test]
+    fn should_forget_old_visits() {
+        let tick = tag("Tick\tActor.h\t/^\tvoid Tick();$/;\"\tp\tline:50\tclass:AActor");
+        let spawn = tag("Spawn\tActor.h\t/^\tvoid Spawn();$/;\"\tp\tline:60\tclass:AActor");
+        let time = 1000 * DAY;
+
+        let mut history = VisitHistory::new();
+        history.record_at(DEFAULT_CLIENT, &tick, time - 400 * DAY);
+        history.record_at("emacs", &spawn, time - 10 * DAY);
+        history.prune(time);
+        assert_eq!(0, history.frecency_at(DEFAULT_CLIENT, &tick, time));
+        assert_eq!(7, history.frecency_at("emacs", &spawn, time));
+    }
+}
@@ -1,2 +1,2 @@
 #!/bin/sh
-ctags --kinds-c++=defgmpstuxzLcnANU --fields-c++=+{properties} --fields=+in --extras=+fr *.h
+ctags --kinds-c++=defgmpstuxzLcnANU --fields-c++=+{properties} --fields=+inSeral --extras=+fr *.h